@@ -1,7 +1,373 @@
-use clap::Parser;
+use crate::config::Config;
+use crate::content::{Clip, ContentKind};
+use crate::editor::edit_externally;
+use crate::history;
+use crate::system::{format_age, unix_now, ClipboardEntry, ClipboardStorage};
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
+use std::{
+    fs,
+    io::{self, IsTerminal, Read},
+};
 
 #[derive(Parser, Debug)]
 pub struct Cli {
     #[arg(short = 'c', long)]
     pub copy: bool,
+
+    /// Print output as JSON instead of plain text
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Headless snippet management, run without opening the popup window.
+/// Entries are addressed by UUID (or a unique UUID prefix) or by nickname.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Save a new snippet (reads stdin when no content is given)
+    Add {
+        content: Option<String>,
+        #[arg(short, long)]
+        nickname: Option<String>,
+        /// Tag the snippet (repeatable)
+        #[arg(short, long = "tag")]
+        tags: Vec<String>,
+    },
+    /// List all saved snippets
+    List {
+        /// Only list snippets with this tag or a tag under it (work matches work/infra)
+        #[arg(short, long)]
+        tag: Option<String>,
+    },
+    /// Print the full content of a snippet
+    Show { id: String },
+    /// Move a snippet to the trash
+    Rm { id: String },
+    /// Replace the content of a snippet (reads stdin when no content is given,
+    /// or opens $EDITOR when stdin is a terminal)
+    Edit { id: String, content: Option<String> },
+    /// Change or clear the nickname of a snippet
+    Rename {
+        id: String,
+        nickname: Option<String>,
+    },
+    /// Add tags to a snippet, or remove them with --remove
+    Tag {
+        id: String,
+        tags: Vec<String>,
+        #[arg(short, long)]
+        remove: bool,
+    },
+    /// Move a snippet to a position in the list (1 is the first)
+    Mv { id: String, position: usize },
+    /// Watch the clipboard in the background and record a history of copies
+    Daemon,
+    /// List snippet backups, or roll back to backup N (1 is the newest)
+    Restore { backup: Option<usize> },
+    /// List deleted snippets, or restore or purge them
+    Trash {
+        #[command(subcommand)]
+        action: Option<TrashCommand>,
+    },
+    /// Inspect config.toml
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TrashCommand {
+    /// Put a deleted snippet back where it was
+    Restore { id: String },
+    /// Permanently delete everything in the trash
+    Empty,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print the problems in config.toml with their line numbers
+    Check,
+}
+
+impl Command {
+    fn modifies_snippets(&self) -> bool {
+        matches!(
+            self,
+            Command::Add { .. }
+                | Command::Rm { .. }
+                | Command::Edit { .. }
+                | Command::Rename { .. }
+                | Command::Tag { .. }
+                | Command::Mv { .. }
+                | Command::Trash {
+                    action: Some(TrashCommand::Restore { .. })
+                }
+        )
+    }
+
+    pub fn run(self, json: bool) -> Result<()> {
+        // checked before loading the config, so it works on a broken file
+        if let Command::Config {
+            action: ConfigCommand::Check,
+        } = self
+        {
+            return check_config(json);
+        }
+
+        let config = Config::load_or_default();
+        let mut storage = ClipboardStorage::load()?;
+        // only commands that write snippets.json anyway purge the trash, so
+        // reading never rewrites the file
+        if self.modifies_snippets() {
+            storage.purge_trash(config.trash_retention_days)?;
+        }
+
+        match self {
+            Command::Add {
+                content,
+                nickname,
+                tags,
+            } => {
+                let content = content_or_stdin(content)?;
+                storage.add_entry(&Clip::Text(content), nickname, tags)?;
+                let entry = storage.get_entries().last().unwrap();
+                if json {
+                    println!("{}", serde_json::to_string_pretty(entry)?);
+                } else {
+                    println!("{}", entry.uuid);
+                }
+            }
+            Command::List { tag } => {
+                let entries: Vec<&ClipboardEntry> = storage
+                    .get_entries()
+                    .iter()
+                    .filter(|e| tag.as_ref().is_none_or(|t| e.has_tag(t)))
+                    .collect();
+
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&entries)?);
+                } else {
+                    for entry in entries {
+                        println!("{}", summary_line(entry));
+                    }
+                }
+            }
+            Command::Show { id } => {
+                let entry = resolve(&storage, &id)?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(entry)?);
+                } else {
+                    print!("{}", entry.content);
+                }
+            }
+            Command::Rm { id } => {
+                let uuid = resolve(&storage, &id)?.uuid.clone();
+                storage.remove_entries(&[uuid])?;
+            }
+            Command::Edit { id, content } => {
+                let entry = resolve(&storage, &id)?;
+                // the text of an HTML snippet is editable, its markup is kept as is
+                if let ContentKind::Image { .. } = entry.kind {
+                    return Err(anyhow!("Image snippets can't be edited."));
+                }
+                let uuid = entry.uuid.clone();
+                let content = match content {
+                    None if io::stdin().is_terminal() => {
+                        edit_externally(&entry.content, &config.editor_command)?
+                    }
+                    content => content_or_stdin(content)?,
+                };
+                storage.update_entry(&uuid, |entry| entry.content = content)?;
+            }
+            Command::Rename { id, nickname } => {
+                let uuid = resolve(&storage, &id)?.uuid.clone();
+                let nickname = nickname.filter(|n| !n.is_empty());
+                storage.update_entry(&uuid, |entry| entry.nickname = nickname)?;
+            }
+            Command::Tag { id, tags, remove } => {
+                let uuid = resolve(&storage, &id)?.uuid.clone();
+                storage.update_entry(&uuid, |entry| {
+                    if remove {
+                        entry
+                            .tags
+                            .retain(|t| !tags.iter().any(|r| r.eq_ignore_ascii_case(t)));
+                    } else {
+                        for tag in tags {
+                            if !entry.tags.iter().any(|t| t.eq_ignore_ascii_case(&tag)) {
+                                entry.tags.push(tag);
+                            }
+                        }
+                    }
+                })?;
+            }
+            Command::Mv { id, position } => {
+                let uuid = resolve(&storage, &id)?.uuid.clone();
+                storage.move_entry(&uuid, position.saturating_sub(1))?;
+            }
+            Command::Daemon => history::run_daemon(&config)?,
+            Command::Restore { backup: Some(n) } => {
+                let restored = ClipboardStorage::restore(n)?;
+                println!(
+                    "Restored backup {} ({} snippets).",
+                    n,
+                    restored.get_entries().len()
+                );
+            }
+            Command::Restore { backup: None } => {
+                let mut backups = Vec::new();
+                for (i, path) in ClipboardStorage::backups()?.iter().enumerate() {
+                    let entries =
+                        ClipboardStorage::read(path).map_or(0, |(b, _)| b.get_entries().len());
+                    let age = path
+                        .metadata()?
+                        .modified()?
+                        .elapsed()
+                        .map_or(0, |d| d.as_secs());
+                    backups.push((i + 1, entries, age));
+                }
+
+                if json {
+                    let backups: Vec<_> = backups
+                        .iter()
+                        .map(|&(n, entries, age)| {
+                            serde_json::json!({ "backup": n, "entries": entries, "age_secs": age })
+                        })
+                        .collect();
+                    println!("{}", serde_json::to_string_pretty(&backups)?);
+                } else if backups.is_empty() {
+                    println!("No backups yet.");
+                } else {
+                    for (n, entries, age) in backups {
+                        println!("{}\t{} snippets\t{}", n, entries, format_age(age));
+                    }
+                }
+            }
+            Command::Config { .. } => unreachable!("handled before loading the config"),
+            Command::Trash { action: None } => {
+                let trash = storage.get_trash();
+                if json {
+                    println!("{}", serde_json::to_string_pretty(trash)?);
+                } else if trash.is_empty() {
+                    println!("The trash is empty.");
+                } else {
+                    let now = unix_now();
+                    for trashed in trash.iter().rev() {
+                        println!(
+                            "{}\tdeleted {}",
+                            summary_line(&trashed.entry),
+                            format_age(now.saturating_sub(trashed.deleted_at))
+                        );
+                    }
+                }
+            }
+            Command::Trash {
+                action: Some(TrashCommand::Restore { id }),
+            } => {
+                let trashed = storage.get_trash().iter().map(|t| &t.entry);
+                let uuid = find_entry(trashed.collect(), &id)?.uuid.clone();
+                storage.restore_trashed(&[uuid])?;
+            }
+            Command::Trash {
+                action: Some(TrashCommand::Empty),
+            } => storage.empty_trash()?,
+        }
+        Ok(())
+    }
+}
+
+/// Finds the saved snippet whose UUID, UUID prefix or nickname is `id`.
+fn resolve<'a>(storage: &'a ClipboardStorage, id: &str) -> Result<&'a ClipboardEntry> {
+    find_entry(storage.get_entries().iter().collect(), id)
+}
+
+fn find_entry<'a>(entries: Vec<&'a ClipboardEntry>, id: &str) -> Result<&'a ClipboardEntry> {
+    if let Some(entry) = entries.iter().find(|e| e.uuid == id) {
+        return Ok(entry);
+    }
+
+    let matches: Vec<&ClipboardEntry> = entries
+        .into_iter()
+        .filter(|e| e.uuid.starts_with(id) || e.nickname.as_deref() == Some(id))
+        .collect();
+
+    match matches[..] {
+        [entry] => Ok(entry),
+        [] => Err(anyhow!("No snippet matches '{}'.", id)),
+        _ => Err(anyhow!(
+            "'{}' matches {} snippets, use the full UUID.",
+            id,
+            matches.len()
+        )),
+    }
+}
+
+/// Prints the problems in config.toml, failing when there are any.
+fn check_config(json: bool) -> Result<()> {
+    let path = Config::config_path();
+    if !path.exists() {
+        println!("{} does not exist, the defaults are used.", path.display());
+        return Ok(());
+    }
+
+    let problems = match Config::parse(&fs::read_to_string(&path)?) {
+        Ok((_, problems)) => problems,
+        Err(syntax_error) => vec![syntax_error],
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&problems)?);
+    } else if problems.is_empty() {
+        println!("{} is valid.", path.display());
+    } else {
+        for problem in &problems {
+            match problem.line {
+                Some(line) => println!("{}:{}: {}", path.display(), line, problem.message),
+                None => println!("{}: {}", path.display(), problem.message),
+            }
+        }
+    }
+
+    match problems.len() {
+        0 => Ok(()),
+        n => Err(anyhow!("{n} problem(s) found in config.toml.")),
+    }
+}
+
+fn content_or_stdin(content: Option<String>) -> Result<String> {
+    match content {
+        Some(content) => Ok(content),
+        None => {
+            let mut buffer = String::new();
+            io::stdin().read_to_string(&mut buffer)?;
+            Ok(buffer)
+        }
+    }
+}
+
+fn summary_line(entry: &ClipboardEntry) -> String {
+    let first_line = entry.content.lines().next().unwrap_or("");
+    let preview: String = first_line.chars().take(60).collect();
+    let ellipsis = if preview.len() < entry.content.trim_end().len() {
+        "..."
+    } else {
+        ""
+    };
+
+    let tags = if entry.tags.is_empty() {
+        String::from("-")
+    } else {
+        entry.tags.join(",")
+    };
+
+    format!(
+        "{}\t{}\t{}\t{}{}",
+        entry.uuid,
+        entry.nickname.as_deref().unwrap_or("-"),
+        tags,
+        preview,
+        ellipsis
+    )
 }
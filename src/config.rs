@@ -1,33 +1,118 @@
-use anyhow::{Ok, Result};
+use crate::fonts::{FontSlant, FontWeight};
+use crate::keys::{KeybindingConfig, Keymap};
+use crate::theme::{Theme, ThemeConfig, LEGACY_KEYS, OPTIONAL_KEYS};
+use anyhow::Result;
 use directories::ProjectDirs;
-use serde::{Deserialize, Serialize};
-use std::fs;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{fmt, fs, ops::RangeInclusive, path::PathBuf};
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Deserialize, Debug, Copy, Serialize, Clone)]
 pub enum ColorConfig {
     RGB(u8, u8, u8),
     RGBA(u8, u8, u8, u8),
 }
 
+/// How the popup orders snippets that rank the same, which without a search
+/// query is all of them.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ListOrder {
+    /// Often and recently pasted snippets first
+    #[default]
+    Frecency,
+    /// The order snippets were saved or moved into
+    Saved,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
-    pub background: ColorConfig,
-    pub cursor: ColorConfig,
     pub font_size: u16,
-    pub selected_color: ColorConfig,
-    pub unselected_color: ColorConfig,
-    pub input_color: ColorConfig,
+    /// Font family name or path to a font file; the system's sans-serif font
+    /// when empty
+    pub font: String,
+    pub font_weight: FontWeight,
+    pub font_style: FontSlant,
+    /// Families or files tried in order when `font` is not installed
+    pub font_fallbacks: Vec<String>,
     pub width: i32,
+    /// Ignored with `auto_height`
     pub height: i32,
+    /// Most result rows shown per page; fewer fit when `height` is too small
+    pub max_rows: usize,
+    /// Size the window to fit the result rows instead of using `height`
+    pub auto_height: bool,
+    /// Show the preview pane beside the list when the popup opens (F3 toggles it)
+    pub preview: bool,
+    pub list_order: ListOrder,
+    /// Open snippets in an external editor instead of the built-in one
+    pub external_editor: bool,
+    /// Command for the external editor, e.g. "alacritty -e nvim". Falls back
+    /// to $VISUAL, then $EDITOR, when empty
+    pub editor_command: String,
+    /// Number of entries the history daemon keeps
+    pub history_limit: usize,
+    /// Copied text larger than this is not recorded in the history
+    pub history_max_entry_bytes: usize,
+    /// How often the history daemon checks the selections
+    pub history_poll_ms: u64,
+    /// Record the PRIMARY (mouse highlight) selection as well as CLIPBOARD
+    pub history_watch_primary: bool,
+    /// Days deleted snippets stay in the trash before they are purged
+    pub trash_retention_days: u64,
+    /// Colors and layout, see theme.rs
+    pub theme: ThemeConfig,
+    /// Keys for the popup's actions, see keys.rs
+    pub keybindings: KeybindingConfig,
+    /// `keybindings` combined with the preset, built once the config is loaded
+    #[serde(skip)]
+    pub keymap: Keymap,
+}
+
+/// Something wrong in config.toml. The setting it is about keeps its default.
+#[derive(Debug, Serialize)]
+pub struct Problem {
+    /// Line of the setting, when it could be found
+    pub line: Option<usize>,
+    pub message: String,
 }
 
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for Problem {}
+
 impl Config {
+    /// Loads config.toml, printing the problems found in it.
     pub fn load() -> Result<Self> {
-        let proj_dir = ProjectDirs::from("com", "cogStudios", "cliphoard")
-            .expect("Could not locate project directory.");
+        let (config, problems) = Self::load_checked()?;
+        for problem in problems {
+            eprintln!("cliphoard: config.toml {problem}");
+        }
+        Ok(config)
+    }
+
+    /// Like `load`, but falls back to the defaults when config.toml can't be
+    /// read at all.
+    pub fn load_or_default() -> Self {
+        Self::load().unwrap_or_else(|e| {
+            eprintln!("cliphoard: config.toml not loaded, using defaults: {e}");
+            Config::default()
+        })
+    }
 
-        let config_dir = proj_dir.config_dir();
-        let config_path = config_dir.join("config.toml");
+    /// Loads config.toml, or creates it with the defaults when missing, along
+    /// with the problems found in it. Only a file that isn't valid TOML fails.
+    pub fn load_checked() -> Result<(Self, Vec<Problem>)> {
+        let config_path = Self::config_path();
+        let config_dir = config_path.parent().expect("Config path has no parent.");
 
         // Create config directory if it doesn't exist
         if !config_dir.exists() {
@@ -36,28 +121,421 @@ impl Config {
 
         // Load or create config file
         if config_path.exists() {
-            let config = fs::read_to_string(&config_path)?;
-            Ok(toml::from_str(&config)?)
+            Ok(Self::parse(&fs::read_to_string(&config_path)?)?)
         } else {
             let default_config = Config::default();
             let config = toml::to_string_pretty(&default_config)?;
             fs::write(config_path, config)?;
-            Ok(default_config)
+            Ok((default_config, Vec::new()))
+        }
+    }
+
+    /// Reads the settings in `source` one by one, so an invalid or out of
+    /// range value only resets that setting to its default. Settings added in
+    /// newer versions are simply missing from older files and take their
+    /// defaults as well.
+    pub fn parse(source: &str) -> Result<(Self, Vec<Problem>), Problem> {
+        let mut table: toml::Table = toml::from_str(source).map_err(|e| Problem {
+            line: e
+                .span()
+                .map(|span| source[..span.start].matches('\n').count() + 1),
+            message: e.message().trim_end().replace('\n', ", "),
+        })?;
+        ThemeConfig::migrate(&mut table);
+
+        let mut problems = Vec::new();
+        let theme = match table.remove("theme") {
+            Some(toml::Value::Table(theme)) => check_theme(theme, source, &mut problems),
+            Some(_) => {
+                problems.push(Problem {
+                    line: key_line(source, "", "theme"),
+                    message: "`theme` must be a table.".to_string(),
+                });
+                ThemeConfig::default()
+            }
+            None => ThemeConfig::default(),
+        };
+        let (keybindings, keymap) = match table.remove("keybindings") {
+            Some(toml::Value::Table(bindings)) => {
+                check_keybindings(bindings, source, &mut problems)
+            }
+            Some(_) => {
+                problems.push(Problem {
+                    line: key_line(source, "", "keybindings"),
+                    message: "`keybindings` must be a table.".to_string(),
+                });
+                Default::default()
+            }
+            None => Default::default(),
+        };
+
+        let known = to_table(&Config::default());
+        let mut accepted = toml::Table::new();
+        for (key, value) in table {
+            let line = key_line(source, "", &key);
+            if !known.contains_key(&key) {
+                problems.push(Problem {
+                    line,
+                    message: format!("Unknown setting `{key}` is ignored."),
+                });
+                continue;
+            }
+            accept::<Config>(&mut accepted, key, value, line, &mut problems);
+        }
+
+        let mut config: Config = toml::Value::Table(accepted).try_into().unwrap_or_default();
+        config.theme = theme;
+        config.keybindings = keybindings;
+        config.keymap = keymap;
+        config.check_ranges(source, &mut problems);
+        problems.sort_by_key(|problem| problem.line.unwrap_or(usize::MAX));
+        Ok((config, problems))
+    }
+
+    /// Resets the settings outside of what the popup can work with.
+    fn check_ranges(&mut self, source: &str, problems: &mut Vec<Problem>) {
+        let defaults = Config::default();
+        let mut check = |key: &str, out_of_range: Option<String>| {
+            if let Some(message) = out_of_range {
+                problems.push(Problem {
+                    line: key_line(source, "", key),
+                    message,
+                });
+                true
+            } else {
+                false
+            }
+        };
+
+        if check(
+            "font_size",
+            out_of_range("font_size", self.font_size, 6..=200),
+        ) {
+            self.font_size = defaults.font_size;
+        }
+        if check("width", out_of_range("width", self.width, 200..=10_000)) {
+            self.width = defaults.width;
+        }
+        if check("height", out_of_range("height", self.height, 50..=10_000)) {
+            self.height = defaults.height;
+        }
+        if check("max_rows", out_of_range("max_rows", self.max_rows, 1..=100)) {
+            self.max_rows = defaults.max_rows;
         }
+        if check(
+            "history_limit",
+            out_of_range("history_limit", self.history_limit, 1..=100_000),
+        ) {
+            self.history_limit = defaults.history_limit;
+        }
+        if check(
+            "history_poll_ms",
+            out_of_range("history_poll_ms", self.history_poll_ms, 50..=60_000),
+        ) {
+            self.history_poll_ms = defaults.history_poll_ms;
+        }
+        if check(
+            "history_max_entry_bytes",
+            out_of_range(
+                "history_max_entry_bytes",
+                self.history_max_entry_bytes,
+                1..=16 * 1024 * 1024,
+            ),
+        ) {
+            self.history_max_entry_bytes = defaults.history_max_entry_bytes;
+        }
+        if check(
+            "trash_retention_days",
+            out_of_range(
+                "trash_retention_days",
+                self.trash_retention_days,
+                0..=36_500,
+            ),
+        ) {
+            self.trash_retention_days = defaults.trash_retention_days;
+        }
+    }
+
+    pub fn config_path() -> PathBuf {
+        let proj_dir = ProjectDirs::from("com", "cogStudios", "cliphoard")
+            .expect("Could not locate project directory.");
+        proj_dir.config_dir().join("config.toml")
     }
 }
 
+/// Message for a `key` whose `value` is outside of `range`.
+pub fn out_of_range<T: PartialOrd + fmt::Display>(
+    key: &str,
+    value: T,
+    range: RangeInclusive<T>,
+) -> Option<String> {
+    (!range.contains(&value)).then(|| {
+        format!(
+            "`{}` is {}, it must be between {} and {}.",
+            key,
+            value,
+            range.start(),
+            range.end()
+        )
+    })
+}
+
+/// Keeps the `[theme]` entries that are valid theme values.
+fn check_theme(theme: toml::Table, source: &str, problems: &mut Vec<Problem>) -> ThemeConfig {
+    let known = to_table(&Theme::default());
+    let mut accepted = toml::Table::new();
+    for (key, value) in theme {
+        // migrated legacy settings are still at the top of the file
+        let line = key_line(source, "theme", &key).or_else(|| {
+            LEGACY_KEYS
+                .contains(&key.as_str())
+                .then(|| key_line(source, "", &key))
+                .flatten()
+        });
+
+        if key == "name" {
+            match value.as_str().map(Theme::named) {
+                Some(Ok(_)) => {
+                    accepted.insert(key, value);
+                }
+                Some(Err(e)) => problems.push(Problem {
+                    line,
+                    message: e.to_string(),
+                }),
+                None => problems.push(Problem {
+                    line,
+                    message: "`name` in [theme] must be a string.".to_string(),
+                }),
+            }
+        } else if !known.contains_key(&key) && !OPTIONAL_KEYS.contains(&key.as_str()) {
+            problems.push(Problem {
+                line,
+                message: format!("Unknown setting `{key}` in [theme] is ignored."),
+            });
+        } else {
+            accept::<Theme>(&mut accepted, key, value, line, problems);
+        }
+    }
+
+    let mut theme = ThemeConfig(accepted);
+    if let Ok(resolved) = theme.resolve() {
+        for (key, message) in resolved.range_problems() {
+            problems.push(Problem {
+                line: key_line(source, "theme", key),
+                message,
+            });
+            theme.0.remove(key);
+        }
+    }
+    theme
+}
+
+/// Keeps the `[keybindings]` entries with valid actions and chords, falling
+/// back to the default bindings when they bind a chord twice. Returns them
+/// with their keymap.
+fn check_keybindings(
+    bindings: toml::Table,
+    source: &str,
+    problems: &mut Vec<Problem>,
+) -> (KeybindingConfig, Keymap) {
+    let mut accepted = toml::Table::new();
+    for (key, value) in bindings {
+        let line = key_line(source, "keybindings", &key);
+        accept::<KeybindingConfig>(&mut accepted, key, value, line, problems);
+    }
+
+    let bindings: KeybindingConfig = toml::Value::Table(accepted).try_into().unwrap_or_default();
+    match Keymap::new(&bindings) {
+        Ok(keymap) => (bindings, keymap),
+        Err(e) => {
+            problems.push(Problem {
+                line: key_line(source, "", "keybindings"),
+                message: format!("{e} The default key bindings are used."),
+            });
+            Default::default()
+        }
+    }
+}
+
+/// Adds `key` to `accepted` when that still deserializes as a `T`, or
+/// reports why it doesn't.
+fn accept<T: DeserializeOwned>(
+    accepted: &mut toml::Table,
+    key: String,
+    value: toml::Value,
+    line: Option<usize>,
+    problems: &mut Vec<Problem>,
+) {
+    let mut candidate = accepted.clone();
+    candidate.insert(key.clone(), value.clone());
+    match toml::Value::Table(candidate).try_into::<T>() {
+        Ok(_) => {
+            accepted.insert(key, value);
+        }
+        Err(e) => problems.push(Problem {
+            line,
+            message: format!(
+                "Invalid `{}`, the default is used: {}",
+                key,
+                e.message().trim_end().replace('\n', ", ")
+            ),
+        }),
+    }
+}
+
+fn to_table<T: Serialize>(value: &T) -> toml::Table {
+    match toml::Value::try_from(value) {
+        Ok(toml::Value::Table(table)) => table,
+        _ => toml::Table::new(),
+    }
+}
+
+/// Line of `key` in the `[section]` of `source`, "" being the top level.
+fn key_line(source: &str, section: &str, key: &str) -> Option<usize> {
+    let mut current = "";
+    for (i, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('[') {
+            current = header
+                .trim_start_matches('[')
+                .split(']')
+                .next()
+                .unwrap_or("")
+                .trim();
+            // a table value gets a header of its own, e.g. [theme.cursor]
+            let table = match section {
+                "" => key.to_string(),
+                section => format!("{section}.{key}"),
+            };
+            if current == table {
+                return Some(i + 1);
+            }
+        } else if current == section {
+            if let Some((name, _)) = line.split_once('=') {
+                if name.trim().trim_matches('"') == key {
+                    return Some(i + 1);
+                }
+            }
+        }
+    }
+    None
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
-            background: ColorConfig::RGBA(60, 56, 42, 80),
-            cursor: ColorConfig::RGB(20, 200, 29),
             font_size: 24,
-            selected_color: ColorConfig::RGB(230, 230, 230),
-            unselected_color: ColorConfig::RGBA(230, 230, 230, 70),
-            input_color: ColorConfig::RGB(255, 255, 255),
+            font: String::new(),
+            font_weight: FontWeight::Bold,
+            font_style: FontSlant::Normal,
+            font_fallbacks: Vec::new(),
             width: 1000,
             height: 230,
+            max_rows: 5,
+            auto_height: false,
+            preview: false,
+            list_order: ListOrder::Frecency,
+            external_editor: false,
+            editor_command: String::new(),
+            history_limit: 200,
+            history_max_entry_bytes: 64 * 1024,
+            history_poll_ms: 500,
+            history_watch_primary: true,
+            trash_retention_days: 30,
+            theme: ThemeConfig::default(),
+            keybindings: KeybindingConfig::default(),
+            keymap: Keymap::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(source: &str) -> Vec<Problem> {
+        Config::parse(source).unwrap().1
+    }
+
+    #[test]
+    fn reports_the_line_of_a_bad_value() {
+        for (source, line) in [
+            ("font_size = \"big\"\nwidth = 800\n", 1),
+            ("width = 800\nheight = true\n", 2),
+            ("[theme]\nname = \"nord\"\nmargin = \"wide\"\n", 3),
+            ("width = 800\n\n[theme.cursor]\nRGB = [1, 2]\n", 3),
+            ("[keybindings]\ndelete = \"Ctrl+Nope\"\n", 2),
+        ] {
+            let problems = problems(source);
+            assert_eq!(problems.len(), 1, "{source}");
+            assert_eq!(problems[0].line, Some(line), "{source}");
+        }
+    }
+
+    #[test]
+    fn keeps_the_valid_settings_around_a_bad_one() {
+        let (config, _) = Config::parse("font_size = \"big\"\nwidth = 800\n").unwrap();
+        assert_eq!(config.font_size, 24);
+        assert_eq!(config.width, 800);
+    }
+
+    #[test]
+    fn resets_out_of_range_values() {
+        let (config, problems) = Config::parse("font_size = 2\nmax_rows = 7\n").unwrap();
+        assert_eq!(config.font_size, 24);
+        assert_eq!(config.max_rows, 7);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].line, Some(1));
+
+        let (config, problems) = Config::parse("[theme]\nopacity = 3.0\n").unwrap();
+        assert_eq!(config.theme.resolve().unwrap().opacity, 0.5);
+        assert_eq!(problems[0].line, Some(2));
+    }
+
+    #[test]
+    fn warns_about_unknown_keys() {
+        for (source, key, line) in [
+            ("colour = 1\n", "colour", 1),
+            ("[theme]\nname = \"nord\"\nfoo = 1\n", "foo", 3),
+        ] {
+            let problems = problems(source);
+            assert_eq!(problems.len(), 1, "{source}");
+            assert_eq!(problems[0].line, Some(line));
+            assert!(problems[0].message.contains(key));
+        }
+        // unset by default, but still known
+        assert!(problems("[theme]\nrow_height = 40\nlist_top = 60\n").is_empty());
+    }
+
+    #[test]
+    fn moves_legacy_colors_into_the_theme() {
+        let (config, problems) = Config::parse("background = { RGB = [1, 2, 3] }\n").unwrap();
+        assert!(problems.is_empty());
+        let theme = config.theme.resolve().unwrap();
+        assert_eq!(format!("{:?}", theme.background), "RGB(1, 2, 3)");
+
+        // the old default doesn't hide the named theme's color
+        let source = "background = { RGBA = [60, 56, 42, 80] }\n[theme]\nname = \"nord\"\n";
+        let theme = Config::parse(source).unwrap().0.theme.resolve().unwrap();
+        assert_eq!(format!("{:?}", theme.background), "RGB(46, 52, 64)");
+
+        // nor does a legacy value override one set in [theme]
+        let source = "cursor = { RGB = [1, 1, 1] }\n[theme]\ncursor = { RGB = [2, 2, 2] }\n";
+        let theme = Config::parse(source).unwrap().0.theme.resolve().unwrap();
+        assert_eq!(format!("{:?}", theme.cursor), "RGB(2, 2, 2)");
+    }
+
+    #[test]
+    fn falls_back_to_default_keys_on_a_conflict() {
+        let (config, problems) =
+            Config::parse("[keybindings]\ndelete = \"Ctrl+E\"\nwidth = 1\n").unwrap();
+        assert!(config.keybindings.bindings.is_empty());
+        assert!(problems.iter().any(|p| p.message.contains("bound to both")));
+    }
+
+    #[test]
+    fn syntax_errors_fail_with_their_line() {
+        let problem = Config::parse("width = 800\nheight = \n").unwrap_err();
+        assert_eq!(problem.line, Some(2));
+    }
+}
@@ -0,0 +1,133 @@
+// Rich clipboard content: plain text, HTML with a plain text fallback, or an
+// image. Entries always keep plain text in their `content` field for searching;
+// `ContentKind` records what else was copied, and images are stored as PNG
+// files in the data directory.
+use anyhow::{anyhow, Result};
+use arboard::{Clipboard, GetExtLinux, ImageData, LinuxClipboardKind};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+/// What an entry holds besides its plain text.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ContentKind {
+    #[default]
+    Text,
+    Html {
+        html: String,
+    },
+    /// Stored as `images/<uuid>.png` in the data directory
+    Image {
+        width: usize,
+        height: usize,
+    },
+}
+
+/// Content read from, or about to be put on, the clipboard.
+pub enum Clip {
+    Text(String),
+    Html {
+        html: String,
+        text: String,
+    },
+    /// 8-bit RGBA pixels, row by row
+    Image {
+        width: usize,
+        height: usize,
+        rgba: Vec<u8>,
+    },
+}
+
+impl Clip {
+    /// Reads `selection`, preferring an image, then HTML, then plain text.
+    pub fn read(clipboard: &mut Clipboard, selection: LinuxClipboardKind) -> Option<Self> {
+        if let Ok(image) = clipboard.get().clipboard(selection).image() {
+            return Some(Clip::Image {
+                width: image.width,
+                height: image.height,
+                rgba: image.bytes.into_owned(),
+            });
+        }
+
+        let text = clipboard.get().clipboard(selection).text().ok();
+        if let Ok(html) = clipboard.get().clipboard(selection).html() {
+            let text = text.unwrap_or_else(|| strip_tags(&html));
+            return Some(Clip::Html { html, text });
+        }
+
+        text.filter(|t| !t.is_empty()).map(Clip::Text)
+    }
+
+    /// Puts the content on the clipboard with its original MIME type.
+    pub fn write(&self, clipboard: &mut Clipboard) -> Result<()> {
+        match self {
+            Clip::Text(text) => clipboard.set_text(text)?,
+            Clip::Html { html, text } => clipboard.set_html(html, Some(text))?,
+            Clip::Image {
+                width,
+                height,
+                rgba,
+            } => clipboard.set_image(ImageData {
+                width: *width,
+                height: *height,
+                bytes: Cow::Borrowed(rgba),
+            })?,
+        }
+        Ok(())
+    }
+
+    /// Plain text stand-in, stored as the entry's searchable content.
+    pub fn text(&self) -> String {
+        match self {
+            Clip::Text(text) | Clip::Html { text, .. } => text.clone(),
+            Clip::Image { width, height, .. } => format!("[image {}x{}]", width, height),
+        }
+    }
+
+    pub fn is_text(&self) -> bool {
+        matches!(self, Clip::Text(_))
+    }
+}
+
+pub fn write_png(path: &Path, width: usize, height: usize, rgba: &[u8]) -> Result<()> {
+    let writer = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(rgba)?;
+    Ok(())
+}
+
+/// Reads a PNG written by `write_png`, returning its size and RGBA pixels.
+pub fn read_png(path: &Path) -> Result<(usize, usize, Vec<u8>)> {
+    let decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    let mut reader = decoder.read_info()?;
+    let mut rgba = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut rgba)?;
+
+    if info.color_type != png::ColorType::Rgba || info.bit_depth != png::BitDepth::Eight {
+        return Err(anyhow!("{} is not an 8-bit RGBA image.", path.display()));
+    }
+    rgba.truncate(info.buffer_size());
+    Ok((info.width as usize, info.height as usize, rgba))
+}
+
+/// Crude text version of `html` for when the clipboard offers no plain text.
+fn strip_tags(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.trim().to_string()
+}
@@ -0,0 +1,474 @@
+// Text editing state for the in-popup snippet editor, plus the fallback of
+// handing a snippet to an external editor. Positions are (row, grapheme
+// column), so the cursor never lands inside an accented letter or emoji.
+use crate::text::{byte_offset, grapheme_count, next_boundary, prev_boundary, truncate_bytes};
+use anyhow::{anyhow, Result};
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::Write,
+    ops::Range,
+    os::unix::fs::OpenOptionsExt,
+    path::PathBuf,
+    process::Command,
+};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Pos {
+    pub row: usize,
+    pub col: usize,
+}
+
+pub struct TextArea {
+    lines: Vec<String>,
+    cursor: Pos,
+    /// Other end of the selection, if one is active
+    anchor: Option<Pos>,
+    /// First row shown in the editor
+    pub scroll: usize,
+}
+
+impl TextArea {
+    pub fn new(text: &str) -> Self {
+        TextArea {
+            lines: text.split('\n').map(String::from).collect(),
+            cursor: Pos { row: 0, col: 0 },
+            anchor: None,
+            scroll: 0,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    pub fn cursor(&self) -> Pos {
+        self.cursor
+    }
+
+    /// Start and end of the selection, in order. `None` when nothing is
+    /// selected.
+    pub fn selection(&self) -> Option<(Pos, Pos)> {
+        let anchor = self.anchor.filter(|&a| a != self.cursor)?;
+        Some((anchor.min(self.cursor), anchor.max(self.cursor)))
+    }
+
+    pub fn selected_text(&self) -> Option<String> {
+        let (start, end) = self.selection()?;
+        if start.row == end.row {
+            let line = &self.lines[start.row];
+            return Some(
+                line[byte_offset(line, start.col)..byte_offset(line, end.col)].to_string(),
+            );
+        }
+
+        let first = &self.lines[start.row];
+        let last = &self.lines[end.row];
+        let mut text = first[byte_offset(first, start.col)..].to_string();
+        for line in &self.lines[start.row + 1..end.row] {
+            text.push('\n');
+            text.push_str(line);
+        }
+        text.push('\n');
+        text.push_str(&last[..byte_offset(last, end.col)]);
+        Some(text)
+    }
+
+    /// Types `text` at the cursor, replacing the selection.
+    pub fn insert(&mut self, text: &str) {
+        self.delete_selection();
+
+        let line = &mut self.lines[self.cursor.row];
+        let tail = line.split_off(byte_offset(line, self.cursor.col));
+
+        for (i, inserted) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.cursor.row += 1;
+                self.lines.insert(self.cursor.row, String::new());
+            }
+            self.lines[self.cursor.row].push_str(inserted);
+        }
+        // counted before the tail goes back on, as a combining mark typed
+        // after a letter joins it rather than adding a column
+        self.cursor.col = grapheme_count(&self.lines[self.cursor.row]);
+        self.lines[self.cursor.row].push_str(&tail);
+    }
+
+    pub fn backspace(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
+
+        if self.cursor.col > 0 {
+            self.cursor.col -= 1;
+            self.remove_grapheme();
+        } else if self.cursor.row > 0 {
+            let line = self.lines.remove(self.cursor.row);
+            self.cursor.row -= 1;
+            self.cursor.col = self.line_len(self.cursor.row);
+            self.lines[self.cursor.row].push_str(&line);
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
+
+        if self.cursor.col < self.line_len(self.cursor.row) {
+            self.remove_grapheme();
+        } else if self.cursor.row + 1 < self.lines.len() {
+            let next = self.lines.remove(self.cursor.row + 1);
+            self.lines[self.cursor.row].push_str(&next);
+        }
+    }
+
+    pub fn move_left(&mut self, select: bool) {
+        self.start_move(select);
+        if self.cursor.col > 0 {
+            self.cursor.col -= 1;
+        } else if self.cursor.row > 0 {
+            self.cursor.row -= 1;
+            self.cursor.col = self.line_len(self.cursor.row);
+        }
+    }
+
+    pub fn move_right(&mut self, select: bool) {
+        self.start_move(select);
+        if self.cursor.col < self.line_len(self.cursor.row) {
+            self.cursor.col += 1;
+        } else if self.cursor.row + 1 < self.lines.len() {
+            self.cursor.row += 1;
+            self.cursor.col = 0;
+        }
+    }
+
+    pub fn move_up(&mut self, select: bool) {
+        self.start_move(select);
+        if self.cursor.row > 0 {
+            self.cursor.row -= 1;
+            self.cursor.col = self.cursor.col.min(self.line_len(self.cursor.row));
+        } else {
+            self.cursor.col = 0;
+        }
+    }
+
+    pub fn move_down(&mut self, select: bool) {
+        self.start_move(select);
+        if self.cursor.row + 1 < self.lines.len() {
+            self.cursor.row += 1;
+            self.cursor.col = self.cursor.col.min(self.line_len(self.cursor.row));
+        } else {
+            self.cursor.col = self.line_len(self.cursor.row);
+        }
+    }
+
+    pub fn home(&mut self, select: bool) {
+        self.start_move(select);
+        self.cursor.col = 0;
+    }
+
+    pub fn end(&mut self, select: bool) {
+        self.start_move(select);
+        self.cursor.col = self.line_len(self.cursor.row);
+    }
+
+    pub fn select_all(&mut self) {
+        self.anchor = Some(Pos { row: 0, col: 0 });
+        let row = self.lines.len() - 1;
+        self.cursor = Pos {
+            row,
+            col: self.line_len(row),
+        };
+    }
+
+    /// Adjusts `scroll` so the cursor row is one of the `visible_rows` shown.
+    pub fn scroll_to_cursor(&mut self, visible_rows: usize) {
+        let visible_rows = visible_rows.max(1);
+        if self.cursor.row < self.scroll {
+            self.scroll = self.cursor.row;
+        } else if self.cursor.row >= self.scroll + visible_rows {
+            self.scroll = self.cursor.row + 1 - visible_rows;
+        }
+    }
+
+    fn line_len(&self, row: usize) -> usize {
+        grapheme_count(&self.lines[row])
+    }
+
+    /// Removes the grapheme right after the cursor.
+    fn remove_grapheme(&mut self) {
+        let line = &mut self.lines[self.cursor.row];
+        let start = byte_offset(line, self.cursor.col);
+        let end = byte_offset(line, self.cursor.col + 1);
+        line.replace_range(start..end, "");
+    }
+
+    /// Starts a selection at the cursor when extending one, clears it when not.
+    fn start_move(&mut self, select: bool) {
+        if !select {
+            self.anchor = None;
+        } else if self.anchor.is_none() {
+            self.anchor = Some(self.cursor);
+        }
+    }
+
+    /// Removes the selected text, returning whether there was any.
+    fn delete_selection(&mut self) -> bool {
+        let Some((start, end)) = self.selection() else {
+            self.anchor = None;
+            return false;
+        };
+
+        let last = &self.lines[end.row];
+        let tail = last[byte_offset(last, end.col)..].to_string();
+        self.lines.drain(start.row + 1..=end.row);
+
+        let first = &mut self.lines[start.row];
+        first.truncate(byte_offset(first, start.col));
+        first.push_str(&tail);
+
+        self.cursor = start;
+        self.anchor = None;
+        true
+    }
+}
+
+/// Longest text the input line holds, in bytes.
+const MAX_INPUT_LEN: usize = 1024;
+
+/// Single-line input with readline-style editing, used for the search query,
+/// nicknames, tags and template values. Positions are byte offsets that
+/// always sit on a grapheme boundary.
+#[derive(Default)]
+pub struct LineInput {
+    text: String,
+    cursor: usize,
+    /// Other end of the selection, if one is active
+    anchor: Option<usize>,
+}
+
+impl LineInput {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Swaps in `text` with the cursor at its end, returning the old text.
+    pub fn replace(&mut self, text: String) -> String {
+        self.cursor = text.len();
+        self.anchor = None;
+        std::mem::replace(&mut self.text, text)
+    }
+
+    pub fn take(&mut self) -> String {
+        self.replace(String::new())
+    }
+
+    /// Byte range of the selection. `None` when nothing is selected.
+    pub fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.anchor.filter(|&a| a != self.cursor)?;
+        Some(anchor.min(self.cursor)..anchor.max(self.cursor))
+    }
+
+    /// Types `text` at the cursor, replacing the selection. Line breaks
+    /// become spaces, and whatever goes past `MAX_INPUT_LEN` is cut off.
+    pub fn insert(&mut self, text: &str) {
+        self.delete_selection();
+        let room = MAX_INPUT_LEN.saturating_sub(self.text.len());
+        let text = truncate_bytes(text, room).replace(['\r', '\n'], " ");
+        self.text.insert_str(self.cursor, &text);
+        self.cursor += text.len();
+    }
+
+    pub fn backspace(&mut self) {
+        if !self.delete_selection() {
+            self.delete_to(prev_boundary(&self.text, self.cursor));
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if !self.delete_selection() && self.cursor < self.text.len() {
+            self.delete_to(next_boundary(&self.text, self.cursor));
+        }
+    }
+
+    pub fn move_left(&mut self, select: bool) {
+        self.move_to(prev_boundary(&self.text, self.cursor), select);
+    }
+
+    pub fn move_right(&mut self, select: bool) {
+        if self.cursor < self.text.len() {
+            self.move_to(next_boundary(&self.text, self.cursor), select);
+        }
+    }
+
+    pub fn word_left(&mut self, select: bool) {
+        self.move_to(self.word_start(), select);
+    }
+
+    pub fn word_right(&mut self, select: bool) {
+        self.move_to(self.word_end(), select);
+    }
+
+    pub fn home(&mut self, select: bool) {
+        self.move_to(0, select);
+    }
+
+    pub fn end(&mut self, select: bool) {
+        self.move_to(self.text.len(), select);
+    }
+
+    /// Deletes the word before the cursor (Ctrl+W).
+    pub fn delete_word(&mut self) {
+        if !self.delete_selection() {
+            self.delete_to(self.word_start());
+        }
+    }
+
+    /// Deletes everything before the cursor (Ctrl+U).
+    pub fn delete_to_start(&mut self) {
+        self.anchor = None;
+        self.delete_to(0);
+    }
+
+    /// Deletes everything after the cursor (Ctrl+K).
+    pub fn delete_to_end(&mut self) {
+        self.anchor = None;
+        self.delete_to(self.text.len());
+    }
+
+    /// Start of the word before the cursor, skipping any spaces and
+    /// punctuation in between.
+    fn word_start(&self) -> usize {
+        let mut pos = self.cursor;
+        let mut in_word = false;
+        while pos > 0 {
+            let prev = prev_boundary(&self.text, pos);
+            let word = is_word(&self.text[prev..pos]);
+            if in_word && !word {
+                break;
+            }
+            in_word |= word;
+            pos = prev;
+        }
+        pos
+    }
+
+    /// End of the word after the cursor, skipping any spaces and punctuation
+    /// in between.
+    fn word_end(&self) -> usize {
+        let mut pos = self.cursor;
+        let mut in_word = false;
+        while pos < self.text.len() {
+            let next = next_boundary(&self.text, pos);
+            let word = is_word(&self.text[pos..next]);
+            if in_word && !word {
+                break;
+            }
+            in_word |= word;
+            pos = next;
+        }
+        pos
+    }
+
+    fn move_to(&mut self, pos: usize, select: bool) {
+        if !select {
+            self.anchor = None;
+        } else if self.anchor.is_none() {
+            self.anchor = Some(self.cursor);
+        }
+        self.cursor = pos;
+    }
+
+    /// Deletes the text between the cursor and `pos`.
+    fn delete_to(&mut self, pos: usize) {
+        let range = pos.min(self.cursor)..pos.max(self.cursor);
+        self.cursor = range.start;
+        self.text.replace_range(range, "");
+    }
+
+    /// Removes the selected text, returning whether there was any.
+    fn delete_selection(&mut self) -> bool {
+        let Some(range) = self.selection() else {
+            self.anchor = None;
+            return false;
+        };
+        self.anchor = None;
+        self.cursor = range.start;
+        self.text.replace_range(range, "");
+        true
+    }
+}
+
+fn is_word(grapheme: &str) -> bool {
+    grapheme.chars().any(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Opens `content` in an external editor and returns the saved result.
+/// `command` may include arguments (e.g. `alacritty -e nvim`); when empty,
+/// `$VISUAL` and then `$EDITOR` are used.
+pub fn edit_externally(content: &str, command: &str) -> Result<String> {
+    let command = match command.trim() {
+        "" => env::var("VISUAL")
+            .or_else(|_| env::var("EDITOR"))
+            .map_err(|_| anyhow!("Neither $VISUAL nor $EDITOR is set."))?,
+        command => command.to_string(),
+    };
+    let mut args = command.split_whitespace();
+    let program = args
+        .next()
+        .ok_or_else(|| anyhow!("Empty editor command."))?;
+
+    let file = TempFile::create(content)?;
+    let status = Command::new(program).args(args).arg(&file.path).status();
+    let edited = fs::read_to_string(&file.path);
+    drop(file);
+
+    if !status?.success() {
+        return Err(anyhow!(
+            "{} exited with an error, snippet not changed.",
+            program
+        ));
+    }
+    Ok(edited?)
+}
+
+/// A file only the current user can read, under a name other users can't
+/// guess. Removed when dropped.
+struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    fn create(content: &str) -> Result<Self> {
+        let path = env::temp_dir().join(format!("cliphoard-{}.txt", Uuid::new_v4()));
+        // create_new refuses to follow a symlink planted at the path
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)?;
+        let temp = TempFile { path };
+        file.write_all(content.as_bytes())?;
+        Ok(temp)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,336 @@
+// Finds the font files for the popup from the `font`, `font_weight`,
+// `font_style` and `font_fallbacks` settings, using font-kit to match family
+// names against the installed fonts. Text is drawn with the first font of the
+// chain that has a glyph for each character, so emoji, CJK and symbols don't
+// turn into boxes when the main font lacks them.
+use crate::config::Config;
+use crate::text::graphemes;
+use anyhow::{anyhow, Result};
+use font_kit::{
+    family_name::FamilyName,
+    handle::Handle,
+    properties::{Properties, Style, Weight},
+    source::SystemSource,
+};
+use sdl2::{
+    pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+    render::BlendMode,
+    surface::Surface,
+    ttf::{self, FontStyle},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::OnceCell,
+    path::{Path, PathBuf},
+};
+
+/// Families tried after the configured fonts, for glyphs none of those have.
+/// Whichever of them are installed are used.
+const DISCOVERED_FALLBACKS: &[&str] = &[
+    "Noto Color Emoji",
+    "Noto Emoji",
+    "Twemoji",
+    "Noto Sans CJK SC",
+    "Noto Sans CJK JP",
+    "WenQuanYi Micro Hei",
+    "Noto Sans Math",
+    "Noto Sans Symbols",
+    "Noto Sans Symbols2",
+    "DejaVu Sans",
+    "Symbola",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FontWeight {
+    Thin,
+    Light,
+    Normal,
+    Medium,
+    Semibold,
+    Bold,
+    Black,
+}
+
+impl From<FontWeight> for Weight {
+    fn from(value: FontWeight) -> Self {
+        match value {
+            FontWeight::Thin => Weight::THIN,
+            FontWeight::Light => Weight::LIGHT,
+            FontWeight::Normal => Weight::NORMAL,
+            FontWeight::Medium => Weight::MEDIUM,
+            FontWeight::Semibold => Weight::SEMIBOLD,
+            FontWeight::Bold => Weight::BOLD,
+            FontWeight::Black => Weight::BLACK,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FontSlant {
+    Normal,
+    Italic,
+    Oblique,
+}
+
+impl From<FontSlant> for Style {
+    fn from(value: FontSlant) -> Self {
+        match value {
+            FontSlant::Normal => Style::Normal,
+            FontSlant::Italic => Style::Italic,
+            FontSlant::Oblique => Style::Oblique,
+        }
+    }
+}
+
+/// A font file and the index of the face to use from it.
+#[derive(PartialEq)]
+pub struct FontFile {
+    pub path: PathBuf,
+    pub index: u32,
+}
+
+/// Resolves `font`, then each of `font_fallbacks`, to the first one that is
+/// installed (or is a font file). Without a configured font the system's
+/// default sans-serif font is used.
+fn primary_font(
+    source: &SystemSource,
+    properties: &Properties,
+    config: &Config,
+) -> Result<FontFile> {
+    let requested: Vec<&str> = std::iter::once(config.font.as_str())
+        .chain(config.font_fallbacks.iter().map(String::as_str))
+        .filter(|name| !name.trim().is_empty())
+        .collect();
+
+    if requested.is_empty() {
+        let handle = source
+            .select_best_match(&[FamilyName::SansSerif], properties)
+            .map_err(|e| anyhow!("No sans-serif font found: {e:?}."))?;
+        return font_file(handle);
+    }
+
+    if let Some(file) = requested
+        .iter()
+        .find_map(|name| find_font(source, name, properties))
+    {
+        return Ok(file);
+    }
+
+    let mut families = source.all_families().unwrap_or_default();
+    families.sort();
+    families.dedup();
+    Err(anyhow!(
+        "None of the fonts {} were found. Available families: {}.",
+        requested
+            .iter()
+            .map(|name| format!("'{name}'"))
+            .collect::<Vec<_>>()
+            .join(", "),
+        families.join(", ")
+    ))
+}
+
+/// The primary font followed by every other installed font of
+/// `font_fallbacks` and `DISCOVERED_FALLBACKS`, without duplicates.
+pub fn font_files(config: &Config) -> Result<Vec<FontFile>> {
+    let source = SystemSource::new();
+    let properties = properties(config);
+
+    let mut files = vec![primary_font(&source, &properties, config)?];
+    let names = config
+        .font_fallbacks
+        .iter()
+        .map(String::as_str)
+        .chain(DISCOVERED_FALLBACKS.iter().copied());
+    for name in names {
+        if let Some(file) = find_font(&source, name, &properties) {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn properties(config: &Config) -> Properties {
+    let mut properties = Properties::new();
+    properties
+        .weight(config.font_weight.into())
+        .style(config.font_style.into());
+    properties
+}
+
+/// The font file `name` refers to, or the best match in the family `name`.
+fn find_font(source: &SystemSource, name: &str, properties: &Properties) -> Option<FontFile> {
+    if is_font_path(name) {
+        let path = Path::new(name);
+        return path.is_file().then(|| FontFile {
+            path: path.to_path_buf(),
+            index: 0,
+        });
+    }
+    source
+        .select_best_match(&[FamilyName::Title(name.to_string())], properties)
+        .ok()
+        .and_then(|handle| font_file(handle).ok())
+}
+
+/// Whether a `font` setting names a file rather than a family.
+fn is_font_path(name: &str) -> bool {
+    name.contains('/')
+        || [".ttf", ".otf", ".ttc"]
+            .iter()
+            .any(|ext| name.to_lowercase().ends_with(ext))
+}
+
+fn font_file(handle: Handle) -> Result<FontFile> {
+    match handle {
+        Handle::Path { path, font_index } => Ok(FontFile {
+            path,
+            index: font_index,
+        }),
+        Handle::Memory { .. } => Err(anyhow!("The selected font is not a file.")),
+    }
+}
+
+/// A font of the chain, and what is needed to look up its glyphs.
+struct ChainFont<'ttf> {
+    font: ttf::Font<'ttf, 'static>,
+    file: FontFile,
+    /// The font parsed again for characters past U+FFFF, which SDL_ttf can't
+    /// look up glyphs for. Only loaded once such a character is drawn
+    glyphs: OnceCell<Option<font_kit::font::Font>>,
+}
+
+impl ChainFont<'_> {
+    fn has_glyph(&self, c: char) -> bool {
+        if u16::try_from(c as u32).is_ok() {
+            return self.font.find_glyph(c).is_some();
+        }
+        self.glyphs
+            .get_or_init(|| font_kit::font::Font::from_path(&self.file.path, self.file.index).ok())
+            .as_ref()
+            .is_some_and(|glyphs| glyphs.glyph_for_char(c).is_some())
+    }
+}
+
+/// The fonts text is drawn with: the primary font, then the fallbacks tried in
+/// order for characters it has no glyph for.
+pub struct FontChain<'ttf> {
+    fonts: Vec<ChainFont<'ttf>>,
+}
+
+impl<'ttf> FontChain<'ttf> {
+    /// Loads the fonts of `font_files`. Fallbacks that fail to load, e.g.
+    /// bitmap-only emoji fonts on older SDL_ttf versions, are left out.
+    pub fn load(ttf: &'ttf ttf::Sdl2TtfContext, config: &Config) -> Result<Self> {
+        let mut fonts = Vec::new();
+        for (i, file) in font_files(config)?.into_iter().enumerate() {
+            match ttf.load_font_at_index(&file.path, file.index, config.font_size) {
+                Ok(font) => fonts.push(ChainFont {
+                    font,
+                    file,
+                    glyphs: OnceCell::new(),
+                }),
+                Err(e) if i == 0 => {
+                    return Err(anyhow!("Could not load {}: {e}", file.path.display()))
+                }
+                Err(_) => {}
+            }
+        }
+        Ok(FontChain { fonts })
+    }
+
+    pub fn height(&self) -> i32 {
+        self.fonts[0].font.height()
+    }
+
+    pub fn set_style(&mut self, style: FontStyle) {
+        for chain_font in &mut self.fonts {
+            chain_font.font.set_style(style);
+        }
+    }
+
+    /// Splits `text` into runs drawn with the same font, returned with the
+    /// index of their font. Graphemes no font has a glyph for use the primary.
+    fn runs<'a>(&self, text: &'a str) -> Vec<(usize, &'a str)> {
+        let mut runs: Vec<(usize, &'a str)> = Vec::new();
+        let mut start = 0;
+        for grapheme in graphemes(text) {
+            let font = grapheme
+                .chars()
+                .next()
+                .and_then(|c| self.fonts.iter().position(|font| font.has_glyph(c)))
+                .unwrap_or(0);
+            let end = start + grapheme.len();
+            match runs.last_mut() {
+                Some((last, run)) if *last == font => *run = &text[start - run.len()..end],
+                _ => runs.push((font, &text[start..end])),
+            }
+            start = end;
+        }
+        runs
+    }
+
+    /// Baseline and height of a line holding `runs`, which fits the tallest
+    /// of their fonts.
+    fn line_metrics(&self, runs: &[(usize, &str)]) -> (i32, i32) {
+        let baseline = runs
+            .iter()
+            .map(|&(font, _)| self.fonts[font].font.ascent())
+            .chain([self.fonts[0].font.ascent()])
+            .max()
+            .unwrap_or(0);
+        let height = runs
+            .iter()
+            .map(|&(font, _)| {
+                let font = &self.fonts[font].font;
+                baseline - font.ascent() + font.height()
+            })
+            .chain([self.height()])
+            .max()
+            .unwrap_or(0);
+        (baseline, height)
+    }
+
+    /// Width and height of `text` when rendered.
+    pub fn size_of(&self, text: &str) -> Result<(u32, u32)> {
+        let runs = self.runs(text);
+        let mut width = 0;
+        for &(font, run) in &runs {
+            width += self.fonts[font].font.size_of(run)?.0;
+        }
+        Ok((width, self.line_metrics(&runs).1 as u32))
+    }
+
+    /// Renders `text` in blended mode, each run with its own font and all of
+    /// them on a shared baseline.
+    pub fn render(&self, text: &str, color: impl Into<Color>) -> Result<Surface<'static>> {
+        let color = color.into();
+        let runs = self.runs(text);
+        if let [(font, run)] = runs[..] {
+            return Ok(self.fonts[font].font.render(run).blended(color)?);
+        }
+
+        let (width, height) = self.size_of(text)?;
+        let mut surface = Surface::new(width.max(1), height, PixelFormatEnum::ARGB8888)
+            .map_err(|e| anyhow!(e))?;
+        let (baseline, _) = self.line_metrics(&runs);
+        let mut x = 0;
+        for (font, run) in runs {
+            let font = &self.fonts[font].font;
+            let mut part = font.render(run).blended(color)?;
+            // copy the pixels as they are, runs never overlap
+            part.set_blend_mode(BlendMode::None)
+                .map_err(|e| anyhow!(e))?;
+            let rect = Rect::new(x, baseline - font.ascent(), part.width(), part.height());
+            part.blit(None, &mut surface, rect)
+                .map_err(|e| anyhow!(e))?;
+            x += part.width() as i32;
+        }
+        Ok(surface)
+    }
+}
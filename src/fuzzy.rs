@@ -0,0 +1,209 @@
+// Scored fuzzy matching, loosely modelled on fzf's algorithm: every query
+// character must appear in order, and the alignment with the best score wins.
+// Contiguous runs and matches at word boundaries are rewarded, gaps between
+// matched characters are penalized. Only the start of long texts is scored, so
+// a few huge snippets don't slow down typing.
+
+const SCORE_MATCH: i32 = 16;
+const GAP_START: i32 = -5;
+const GAP_EXTEND: i32 = -1;
+const BONUS_CONSECUTIVE: i32 = 12;
+const BONUS_PREFIX: i32 = 8;
+const BONUS_BOUNDARY_WHITE: i32 = 10;
+const BONUS_BOUNDARY_DELIMITER: i32 = 9;
+const BONUS_CAMEL: i32 = 7;
+
+const UNMATCHED: i32 = i32::MIN / 2;
+
+/// Chars of a text that are scored. Past them a text only has to contain the
+/// query, and matches there get a score of zero.
+const MAX_SCORED_CHARS: usize = 1000;
+
+#[derive(Debug, Clone, Default)]
+pub struct FuzzyMatch {
+    pub score: i32,
+    /// Char indices into the matched text, in ascending order.
+    pub positions: Vec<usize>,
+}
+
+/// Case-insensitively matches `query` against `text`, returning `None` when
+/// `text` does not contain every query character in order. An empty query
+/// matches everything with a score of zero.
+pub fn fuzzy_match(query: &str, text: &str) -> Option<FuzzyMatch> {
+    let query: Vec<char> = query.chars().map(fold_case).collect();
+    if query.is_empty() {
+        return Some(FuzzyMatch::default());
+    }
+
+    // cheap check before allocating anything for the text
+    let first_match = leftmost_positions(&query, text)?;
+
+    let original: Vec<char> = text.chars().take(MAX_SCORED_CHARS).collect();
+    let text: Vec<char> = original.iter().copied().map(fold_case).collect();
+    let (n, m) = (query.len(), text.len());
+    if first_match[n - 1] >= m {
+        return Some(FuzzyMatch {
+            score: 0,
+            positions: first_match,
+        });
+    }
+
+    let bonus: Vec<i32> = (0..m)
+        .map(|j| match j {
+            0 => BONUS_BOUNDARY_WHITE + BONUS_PREFIX,
+            _ => boundary_bonus(original[j - 1], original[j]),
+        })
+        .collect();
+
+    // scores[i * m + j]: best score of query[..=i] with query[i] at text[j]
+    // from[i * m + j]: where query[i - 1] was matched on that best path
+    let mut scores = vec![UNMATCHED; n * m];
+    let mut from = vec![0usize; n * m];
+
+    for i in 0..n {
+        let mut gap_best = UNMATCHED;
+        let mut gap_from = 0;
+
+        for j in 0..m {
+            if i > 0 && j >= 2 {
+                gap_best += GAP_EXTEND;
+                let candidate = scores[(i - 1) * m + j - 2] + GAP_START;
+                if candidate > gap_best {
+                    gap_best = candidate;
+                    gap_from = j - 2;
+                }
+            }
+
+            if text[j] != query[i] {
+                continue;
+            }
+
+            let cell = i * m + j;
+            if i == 0 {
+                scores[cell] = SCORE_MATCH + bonus[j];
+                continue;
+            }
+
+            let diagonal = match j {
+                0 => UNMATCHED,
+                _ => scores[cell - m - 1] + BONUS_CONSECUTIVE,
+            };
+            let (best, prev) = if diagonal >= gap_best {
+                (diagonal, j.saturating_sub(1))
+            } else {
+                (gap_best, gap_from)
+            };
+
+            if best > UNMATCHED / 2 {
+                scores[cell] = best + SCORE_MATCH + bonus[j];
+                from[cell] = prev;
+            }
+        }
+    }
+
+    let last_row = &scores[(n - 1) * m..];
+    let (mut j, &score) = last_row
+        .iter()
+        .enumerate()
+        .max_by_key(|&(j, &s)| (s, std::cmp::Reverse(j)))?;
+    if score <= UNMATCHED / 2 {
+        return None;
+    }
+
+    let mut positions = vec![0; n];
+    for i in (0..n).rev() {
+        positions[i] = j;
+        j = from[i * m + j];
+    }
+
+    Some(FuzzyMatch { score, positions })
+}
+
+fn fold_case(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Char indices of the first occurrence of each query char in order, or
+/// `None` when `text` doesn't contain them all.
+fn leftmost_positions(query: &[char], text: &str) -> Option<Vec<usize>> {
+    let mut text = text.chars().map(fold_case).enumerate();
+    query
+        .iter()
+        .map(|&qc| text.find(|&(_, tc)| tc == qc).map(|(j, _)| j))
+        .collect()
+}
+
+fn boundary_bonus(prev: char, current: char) -> i32 {
+    if prev.is_whitespace() {
+        BONUS_BOUNDARY_WHITE
+    } else if !prev.is_alphanumeric() && current.is_alphanumeric() {
+        BONUS_BOUNDARY_DELIMITER
+    } else if (prev.is_lowercase() && current.is_uppercase())
+        || (!prev.is_numeric() && current.is_numeric())
+    {
+        BONUS_CAMEL
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(query: &str, text: &str) -> Vec<usize> {
+        fuzzy_match(query, text).unwrap().positions
+    }
+
+    fn score(query: &str, text: &str) -> i32 {
+        fuzzy_match(query, text).unwrap().score
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        let result = fuzzy_match("", "anything").unwrap();
+        assert_eq!(result.score, 0);
+        assert!(result.positions.is_empty());
+    }
+
+    #[test]
+    fn requires_every_char_in_order() {
+        assert!(fuzzy_match("abc", "cba").is_none());
+        assert!(fuzzy_match("abcd", "abc").is_none());
+        assert!(fuzzy_match("x", "").is_none());
+        assert!(fuzzy_match("gp", "git push").is_some());
+    }
+
+    #[test]
+    fn ignores_case() {
+        assert_eq!(positions("GIT", "git push"), [0, 1, 2]);
+        assert_eq!(positions("push", "Git PUSH"), [4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn positions_are_char_indices() {
+        assert_eq!(positions("ß", "straße"), [4]);
+        assert_eq!(positions("e", "café e"), [5]);
+    }
+
+    #[test]
+    fn prefers_word_boundaries_and_runs() {
+        // "p" of "push" rather than the one in "ssh_pid"
+        assert_eq!(positions("gp", "git ssh_pid push"), [0, 8]);
+        assert_eq!(positions("ush", "us ush"), [3, 4, 5]);
+        assert!(score("dep", "deploy") > score("dep", "d_e_p"));
+        assert!(score("dc", "docker compose") > score("dc", "abducted"));
+    }
+
+    #[test]
+    fn long_texts_are_scored_only_at_the_start() {
+        let long = "x".repeat(MAX_SCORED_CHARS) + "needle";
+        let result = fuzzy_match("needle", &long).unwrap();
+        assert_eq!(result.score, 0);
+        assert_eq!(result.positions[0], MAX_SCORED_CHARS);
+
+        let early = "needle".to_string() + &"x".repeat(10 * MAX_SCORED_CHARS);
+        assert!(score("needle", &early) > 0);
+        assert!(fuzzy_match("needles", &early).is_none());
+    }
+}
@@ -0,0 +1,127 @@
+use crate::config::Config;
+use crate::system::{unix_now, write_atomic};
+use anyhow::{Ok, Result};
+use arboard::{Clipboard, GetExtLinux, LinuxClipboardKind};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::{fs::File, path::PathBuf, thread, time::Duration};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Selection {
+    Clipboard,
+    Primary,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub uuid: String,
+    pub content: String,
+    pub selection: Selection,
+    /// Unix timestamp (seconds) of the last time this text was copied
+    pub copied_at: u64,
+}
+
+/// Automatically recorded clipboard history, kept apart from the curated
+/// snippets in `ClipboardStorage`. Newest entries come first.
+#[derive(Serialize, Deserialize, Default)]
+pub struct ClipboardHistory {
+    entries: Vec<HistoryEntry>,
+}
+
+impl ClipboardHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load() -> Result<Self> {
+        let path = Self::storage_path()?;
+
+        if !path.exists() {
+            return Ok(Self::new());
+        }
+
+        let file = File::open(path)?;
+        let history: Self = serde_json::from_reader(file)?;
+        Ok(history)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        write_atomic(&Self::storage_path()?, self)
+    }
+
+    /// Moves `content` to the front of the history, dropping any older copy
+    /// of the same text and trimming the history to `limit` entries.
+    pub fn record(&mut self, content: String, selection: Selection, limit: usize) {
+        let copied_at = unix_now();
+
+        let uuid = match self.entries.iter().position(|e| e.content == content) {
+            Some(index) => self.entries.remove(index).uuid,
+            None => Uuid::new_v4().to_string(),
+        };
+
+        self.entries.insert(
+            0,
+            HistoryEntry {
+                uuid,
+                content,
+                selection,
+                copied_at,
+            },
+        );
+        self.entries.truncate(limit);
+    }
+
+    pub fn get_entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    pub fn storage_path() -> Result<PathBuf> {
+        let dir = ProjectDirs::from("com", "cogStudios", "cliphoard")
+            .ok_or_else(|| anyhow::anyhow!("Could not locate project directory."))?;
+        Ok(dir.data_dir().join("history.json"))
+    }
+}
+
+/// Polls the CLIPBOARD (and optionally PRIMARY) selection forever, recording
+/// every new piece of text into the history file.
+pub fn run_daemon(config: &Config) -> Result<()> {
+    let mut clipboard = Clipboard::new()?;
+    let mut history = ClipboardHistory::load()?;
+    let mut last_clipboard: Option<String> = None;
+    let mut last_primary: Option<String> = None;
+
+    let mut selections = vec![(Selection::Clipboard, LinuxClipboardKind::Clipboard)];
+    if config.history_watch_primary {
+        selections.push((Selection::Primary, LinuxClipboardKind::Primary));
+    }
+
+    loop {
+        for &(selection, kind) in &selections {
+            let Some(text) = clipboard.get().clipboard(kind).text().ok() else {
+                continue;
+            };
+
+            let last_seen = match selection {
+                Selection::Clipboard => &mut last_clipboard,
+                Selection::Primary => &mut last_primary,
+            };
+            if last_seen.as_deref() == Some(text.as_str()) {
+                continue;
+            }
+            *last_seen = Some(text.clone());
+
+            if text.trim().is_empty() || text.len() > config.history_max_entry_bytes {
+                continue;
+            }
+
+            history.record(text, selection, config.history_limit);
+            if let Err(e) = history.save() {
+                eprintln!("cliphoard: failed to save history: {e}");
+            }
+        }
+
+        thread::sleep(Duration::from_millis(config.history_poll_ms));
+    }
+}
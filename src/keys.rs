@@ -0,0 +1,281 @@
+// Configurable key bindings for the popup. The `[keybindings]` table in
+// config.toml picks a preset and may rebind any action, e.g.
+//
+//   [keybindings]
+//   preset = "vim"
+//   delete = "Ctrl+Shift+D"
+//   rename = ["Ctrl+R", "F2"]
+//
+// Keys are SDL key names ("Return", "PageUp", "F2", "Z"), optionally prefixed
+// with Ctrl+, Shift+ and Alt+. Left and right modifiers are treated the same.
+// Keys that type text ("J", "Shift+J", "Space") need Ctrl or Alt, otherwise
+// they would also end up in the input line.
+// Editing keys of the input line and the snippet editor are not configurable.
+use anyhow::{anyhow, Result};
+use sdl2::keyboard::{Keycode, Mod};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{collections::BTreeMap, fmt};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Up,
+    Down,
+    /// Paste the selection, or confirm the current prompt
+    Select,
+    /// Close the popup, or back out of the current prompt
+    Quit,
+    Delete,
+    Edit,
+    Rename,
+    Undo,
+    Redo,
+    Mark,
+    Tag,
+    Export,
+    SwitchView,
+    TogglePreview,
+    MoveUp,
+    MoveDown,
+    PreviewUp,
+    PreviewDown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    #[default]
+    Default,
+    /// Adds Ctrl+J / Ctrl+K to move down and up
+    Vim,
+    /// Adds Ctrl+N / Ctrl+P to move down and up
+    Emacs,
+}
+
+/// A key together with the modifiers that have to be held for it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chord {
+    key: Keycode,
+    ctrl: bool,
+    shift: bool,
+    alt: bool,
+}
+
+impl Chord {
+    pub fn parse(chord: &str) -> Result<Self> {
+        // "Ctrl++" binds the plus key itself
+        let (modifiers, key) = match chord.strip_suffix("++") {
+            Some(modifiers) => (modifiers, "+"),
+            None => chord.rsplit_once('+').unwrap_or(("", chord)),
+        };
+
+        let key = match key.trim() {
+            "Enter" => "Return",
+            "Esc" => "Escape",
+            key => key,
+        };
+        let mut parsed = Chord {
+            key: Keycode::from_name(key)
+                .ok_or_else(|| anyhow!("Unknown key '{}' in '{}'.", key, chord))?,
+            ctrl: false,
+            shift: false,
+            alt: false,
+        };
+        for modifier in modifiers
+            .split('+')
+            .map(str::trim)
+            .filter(|m| !m.is_empty())
+        {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => parsed.ctrl = true,
+                "shift" => parsed.shift = true,
+                "alt" => parsed.alt = true,
+                _ => return Err(anyhow!("Unknown modifier '{}' in '{}'.", modifier, chord)),
+            }
+        }
+        if !parsed.ctrl && !parsed.alt && types_text(parsed.key) {
+            return Err(anyhow!(
+                "'{}' would also type text, add Ctrl+ or Alt+.",
+                chord
+            ));
+        }
+        Ok(parsed)
+    }
+
+    /// Whether this is the key pressed with exactly these modifiers held.
+    pub fn matches(&self, key: Keycode, keymod: Mod) -> bool {
+        self.key == key
+            && self.ctrl == keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD)
+            && self.shift == keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD)
+            && self.alt == keymod.intersects(Mod::LALTMOD | Mod::RALTMOD)
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (held, name) in [
+            (self.ctrl, "Ctrl+"),
+            (self.shift, "Shift+"),
+            (self.alt, "Alt+"),
+        ] {
+            if held {
+                f.write_str(name)?;
+            }
+        }
+        f.write_str(&self.key.name())
+    }
+}
+
+/// One or more chords bound to an action. Written as a single string or a
+/// list of strings in the config file.
+#[derive(Debug, Clone)]
+pub struct Chords(pub Vec<Chord>);
+
+impl Serialize for Chords {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().map(Chord::to_string))
+    }
+}
+
+impl<'de> Deserialize<'de> for Chords {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ChordsVisitor;
+
+        impl<'de> de::Visitor<'de> for ChordsVisitor {
+            type Value = Chords;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a key chord like \"Ctrl+D\" or a list of them")
+            }
+
+            fn visit_str<E: de::Error>(self, chord: &str) -> Result<Chords, E> {
+                Chord::parse(chord)
+                    .map(|chord| Chords(vec![chord]))
+                    .map_err(E::custom)
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Chords, A::Error> {
+                let mut chords = Vec::new();
+                while let Some(chord) = seq.next_element::<String>()? {
+                    chords.push(Chord::parse(&chord).map_err(de::Error::custom)?);
+                }
+                Ok(Chords(chords))
+            }
+        }
+
+        deserializer.deserialize_any(ChordsVisitor)
+    }
+}
+
+/// The `[keybindings]` table of config.toml.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct KeybindingConfig {
+    pub preset: Preset,
+    /// Actions bound to other keys than the preset's
+    #[serde(flatten)]
+    pub bindings: BTreeMap<Action, Chords>,
+}
+
+/// Looks up the action bound to a key press.
+#[derive(Debug)]
+pub struct Keymap {
+    bindings: Vec<(Chord, Action)>,
+}
+
+impl Keymap {
+    /// Combines the preset with the configured bindings, which replace the
+    /// preset's chords for their action. Fails when a chord ends up bound to
+    /// two actions.
+    pub fn new(config: &KeybindingConfig) -> Result<Self> {
+        let mut actions: BTreeMap<Action, Vec<&str>> = BTreeMap::new();
+        for &(action, chords) in DEFAULT_BINDINGS {
+            actions.insert(action, chords.to_vec());
+        }
+        let extra: &[(Action, &str)] = match config.preset {
+            Preset::Default => &[],
+            Preset::Vim => &[(Action::Up, "Ctrl+K"), (Action::Down, "Ctrl+J")],
+            Preset::Emacs => &[(Action::Up, "Ctrl+P"), (Action::Down, "Ctrl+N")],
+        };
+        for &(action, chord) in extra {
+            actions.entry(action).or_default().push(chord);
+        }
+
+        let mut bindings: Vec<(Chord, Action)> = Vec::new();
+        for (action, chords) in actions {
+            if config.bindings.contains_key(&action) {
+                continue;
+            }
+            for chord in chords {
+                bindings.push((Chord::parse(chord)?, action));
+            }
+        }
+        for (&action, chords) in &config.bindings {
+            for &chord in &chords.0 {
+                bindings.push((chord, action));
+            }
+        }
+
+        for (i, (chord, action)) in bindings.iter().enumerate() {
+            if let Some((_, other)) = bindings[..i].iter().find(|(c, _)| c == chord) {
+                return Err(anyhow!(
+                    "{} is bound to both {} and {} in [keybindings].",
+                    chord,
+                    action_name(*other),
+                    action_name(*action)
+                ));
+            }
+        }
+
+        Ok(Keymap { bindings })
+    }
+
+    pub fn action(&self, key: Keycode, keymod: Mod) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(chord, _)| chord.matches(key, keymod))
+            .map(|&(_, action)| action)
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::new(&KeybindingConfig::default()).expect("The default key bindings conflict.")
+    }
+}
+
+/// Whether pressing `key` without Ctrl or Alt types a character.
+fn types_text(key: Keycode) -> bool {
+    let name = key.name();
+    let name = name.strip_prefix("Keypad ").unwrap_or(&name);
+    name.chars().count() == 1 || key == Keycode::Space
+}
+
+/// Name of `action` as written in config.toml.
+fn action_name(action: Action) -> String {
+    serde_json::to_value(action)
+        .ok()
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or_default()
+}
+
+const DEFAULT_BINDINGS: &[(Action, &[&str])] = &[
+    (Action::Up, &["Up"]),
+    (Action::Down, &["Down"]),
+    (Action::Select, &["Return", "Keypad Enter"]),
+    (Action::Quit, &["Escape"]),
+    (Action::Delete, &["Ctrl+D"]),
+    (Action::Edit, &["Ctrl+E"]),
+    (Action::Rename, &["Ctrl+R", "F2"]),
+    (Action::Undo, &["Ctrl+Z"]),
+    (Action::Redo, &["Ctrl+Shift+Z"]),
+    (Action::Mark, &["Ctrl+Space"]),
+    (Action::Tag, &["Ctrl+T"]),
+    (Action::Export, &["Ctrl+S"]),
+    (Action::SwitchView, &["Tab"]),
+    (Action::TogglePreview, &["F3"]),
+    (Action::MoveUp, &["Alt+Up"]),
+    (Action::MoveDown, &["Alt+Down"]),
+    (Action::PreviewUp, &["PageUp"]),
+    (Action::PreviewDown, &["PageDown"]),
+];
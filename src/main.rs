@@ -7,20 +7,40 @@ use ui::DClipWindow;
 
 mod cli;
 mod config;
+mod content;
+mod editor;
+mod fonts;
+mod fuzzy;
+mod history;
+mod keys;
 mod system;
+mod template;
+mod text;
+mod theme;
 mod ui;
+mod undo;
 
 #[global_allocator]
 static PEAK_ALLOC: PeakAlloc = PeakAlloc;
 
 fn main() {
+    let cli = cli::Cli::parse();
+
+    // Headless subcommands never open the window
+    if let Some(command) = cli.command {
+        if let Err(e) = command.run(cli.json) {
+            eprintln!("cliphoard: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // Load or create user config file
-    let config = config::Config::load().expect("Failed to load config file.");
+    let config = config::Config::load_or_default();
 
     // Initialize enigo for keyboard controls (copy and paste)
     let mut enigo = Enigo::new(&Settings::default()).expect("Failed to initialize enigo.");
 
-    let cli = cli::Cli::parse();
     println!("{}", sdl2::version::version());
 
     let active_window = Command::new("xdotool")
@@ -32,12 +52,13 @@ fn main() {
 
     // Create and launch window
     let mut dclip_window = DClipWindow::new(cli.copy, config);
-    let paste_text = dclip_window.launch();
+    let paste = dclip_window.launch();
 
-    if let Some(snippet) = paste_text {
+    if let Some(snippet) = paste {
         let mut ctx: Clipboard = Clipboard::new().unwrap();
-        ctx.set_text(snippet)
-            .expect("Failed to set text to clipboard.");
+        snippet
+            .write(&mut ctx)
+            .expect("Failed to set snippet to clipboard.");
         let _ = ctx.get_text();
 
         if let Some(win_id) = &active_window {
@@ -46,11 +67,17 @@ fn main() {
                 .status();
         }
 
+        // Ctrl+Shift+V pastes text in terminals too, but pastes without
+        // formatting in most other apps, so rich content uses Ctrl+V
         let _ = enigo.key(Key::Control, Press);
-        let _ = enigo.key(Key::Shift, Press);
+        if snippet.is_text() {
+            let _ = enigo.key(Key::Shift, Press);
+        }
         let _ = enigo.key(Key::Unicode('v'), Click);
         let _ = enigo.key(Key::Control, Release);
-        let _ = enigo.key(Key::Shift, Release);
+        if snippet.is_text() {
+            let _ = enigo.key(Key::Shift, Release);
+        }
     }
     let peak_mem = PEAK_ALLOC.peak_usage_as_mb();
     println!("The max amount that was used: {}mb", peak_mem);
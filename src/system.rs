@@ -1,22 +1,191 @@
+use crate::content::{read_png, write_png, Clip, ContentKind};
 use anyhow::{Ok, Result};
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::{
-    fs::{self, File},
-    path::PathBuf,
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
 };
 use uuid::Uuid;
 
+/// Number of previous versions of snippets.json kept in the backups directory.
+pub const BACKUP_COUNT: usize = 5;
+
+/// Format version written to snippets.json. Bump it together with a new
+/// entry in `MIGRATIONS` whenever the file layout changes.
+pub const STORAGE_VERSION: u64 = 4;
+
+/// `MIGRATIONS[i]` upgrades a version `i + 1` file to version `i + 2`.
+const MIGRATIONS: &[fn(&mut Value)] = &[
+    // 1 -> 2: tags and usage statistics on every entry
+    |storage| {
+        for entry in entries_mut(storage) {
+            entry
+                .entry("tags")
+                .or_insert_with(|| Value::Array(Vec::new()));
+            for field in ["created_at", "last_used_at", "use_count"] {
+                entry.entry(field).or_insert_with(|| Value::from(0));
+            }
+        }
+    },
+    // 2 -> 3: trash of soft-deleted entries
+    |storage| {
+        if let Some(storage) = storage.as_object_mut() {
+            storage
+                .entry("trash")
+                .or_insert_with(|| Value::Array(Vec::new()));
+        }
+    },
+    // 3 -> 4: content kind, everything before was plain text
+    |storage| {
+        for entry in entries_mut(storage) {
+            entry
+                .entry("kind")
+                .or_insert_with(|| serde_json::json!({ "type": "text" }));
+        }
+    },
+];
+
+fn entries_mut(storage: &mut Value) -> impl Iterator<Item = &mut Map<String, Value>> {
+    storage
+        .get_mut("entries")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object_mut)
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ClipboardEntry {
     pub uuid: String,
     pub content: String,
     pub nickname: Option<String>,
+    /// Tags used to scope searches; a `/` in a tag nests it like a folder
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Unix timestamps in seconds, zero when unknown (saved before tracking)
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub last_used_at: u64,
+    #[serde(default)]
+    pub use_count: u32,
+    /// Plain text, HTML or an image; `content` holds the text in every case
+    #[serde(default)]
+    pub kind: ContentKind,
+}
+
+impl ClipboardEntry {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        has_tag(&self.tags, tag)
+    }
+
+    /// Combined frequency and recency score: every use counts, but recent
+    /// uses count for more. Never-used snippets score zero.
+    pub fn frecency(&self, now: u64) -> u64 {
+        const DAY: u64 = 24 * 60 * 60;
+
+        let weight = match now.saturating_sub(self.last_used_at) {
+            _ if self.use_count == 0 => 0,
+            age if age < DAY => 100,
+            age if age < 4 * DAY => 80,
+            age if age < 14 * DAY => 60,
+            age if age < 31 * DAY => 40,
+            age if age < 90 * DAY => 20,
+            _ => 10,
+        };
+        weight * self.use_count as u64
+    }
+
+    /// The entry as it should be put back on the clipboard.
+    pub fn clip(&self) -> Result<Clip> {
+        Ok(match &self.kind {
+            ContentKind::Text => Clip::Text(self.content.clone()),
+            ContentKind::Html { html } => Clip::Html {
+                html: html.clone(),
+                text: self.content.clone(),
+            },
+            ContentKind::Image { .. } => {
+                let (width, height, rgba) = read_png(&ClipboardStorage::image_path(&self.uuid)?)?;
+                Clip::Image {
+                    width,
+                    height,
+                    rgba,
+                }
+            }
+        })
+    }
+
+    /// Deletes the stored image of an image entry, once it is gone for good.
+    fn remove_image(&self) {
+        if let ContentKind::Image { .. } = self.kind {
+            if let Result::Ok(path) = ClipboardStorage::image_path(&self.uuid) {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Rough human-readable form of an age in seconds, e.g. "3 h ago".
+pub fn format_age(secs: u64) -> String {
+    match secs {
+        0..60 => String::from("just now"),
+        60..3600 => format!("{} min ago", secs / 60),
+        3600..86400 => format!("{} h ago", secs / 3600),
+        _ => format!("{} days ago", secs / 86400),
+    }
+}
+
+/// Whether any of `tags` is `tag` or filed under it, ignoring case. `work`
+/// therefore matches `work` and `work/infra`, but not `workout`.
+pub fn has_tag(tags: &[String], tag: &str) -> bool {
+    let tag = tag.trim_end_matches('/').to_lowercase();
+    tags.iter().any(|t| {
+        let t = t.to_lowercase();
+        t == tag
+            || t.strip_prefix(&tag)
+                .is_some_and(|rest| rest.starts_with('/'))
+    })
+}
+
+/// A deleted entry, kept until it is restored or purged.
+#[derive(Serialize, Deserialize)]
+pub struct TrashedEntry {
+    #[serde(flatten)]
+    pub entry: ClipboardEntry,
+    /// Unix timestamp (seconds) of the deletion
+    pub deleted_at: u64,
+    /// Position the entry had before it was deleted
+    pub position: usize,
 }
 
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Serialize, Deserialize)]
 pub struct ClipboardStorage {
+    version: u64,
     entries: Vec<ClipboardEntry>,
+    /// Soft-deleted entries, oldest deletion first
+    #[serde(default)]
+    trash: Vec<TrashedEntry>,
+}
+
+impl Default for ClipboardStorage {
+    fn default() -> Self {
+        Self {
+            version: STORAGE_VERSION,
+            entries: Vec::new(),
+            trash: Vec::new(),
+        }
+    }
 }
 
 impl ClipboardStorage {
@@ -24,6 +193,9 @@ impl ClipboardStorage {
         Self::default()
     }
 
+    /// Loads snippets.json, upgrading it in memory when it was written by an
+    /// older cliphoard. The original file is copied to the backups directory
+    /// before the upgraded version is first saved over it.
     pub fn load() -> Result<Self> {
         let path = Self::storage_path()?;
 
@@ -31,49 +203,434 @@ impl ClipboardStorage {
             return Ok(Self::new());
         }
 
-        let file = File::open(path)?;
-        let storage: Self = serde_json::from_reader(file)?;
+        let (storage, from_version) = Self::read(&path)?;
+        if from_version < STORAGE_VERSION {
+            let dir = Self::backup_dir()?;
+            let original = dir.join(format!("snippets.v{}.json", from_version));
+            if !original.exists() {
+                fs::create_dir_all(&dir)?;
+                fs::copy(&path, original)?;
+            }
+        }
         Ok(storage)
     }
 
+    /// Parses a snippet file of any known version, migrating it to the
+    /// current one. Also returns the version the file was written in.
+    pub fn read(path: &Path) -> Result<(Self, u64)> {
+        let file = File::open(path)?;
+        let mut value: Value = serde_json::from_reader(file)?;
+
+        // Files from before versioning have no version field
+        let version = value.get("version").and_then(Value::as_u64).unwrap_or(1);
+        if version > STORAGE_VERSION {
+            return Err(anyhow::anyhow!(
+                "{} was written by a newer cliphoard (format version {}, this build \
+                 understands up to {}). Upgrade cliphoard to use it.",
+                path.display(),
+                version,
+                STORAGE_VERSION
+            ));
+        }
+
+        for migrate in &MIGRATIONS[(version.max(1) - 1) as usize..] {
+            migrate(&mut value);
+        }
+        if let Some(storage) = value.as_object_mut() {
+            storage.insert("version".into(), Value::from(STORAGE_VERSION));
+        }
+
+        Ok((serde_json::from_value(value)?, version))
+    }
+
     pub fn save(&self) -> Result<()> {
         let path = Self::storage_path()?;
-        let parent = path.parent().unwrap();
 
-        if !parent.exists() {
-            fs::create_dir(parent)?;
+        if path.exists() {
+            Self::rotate_backups(&path)?;
         }
 
-        let file = File::create(path)?;
-        serde_json::to_writer_pretty(file, self)?;
+        write_atomic(&path, self)
+    }
+
+    /// Shifts `snippets.json.1..N` up by one and copies the current file into
+    /// slot 1, dropping the oldest backup.
+    fn rotate_backups(path: &Path) -> Result<()> {
+        let dir = Self::backup_dir()?;
+        fs::create_dir_all(&dir)?;
+
+        for n in (1..BACKUP_COUNT).rev() {
+            let from = dir.join(format!("snippets.json.{}", n));
+            if from.exists() {
+                fs::rename(&from, dir.join(format!("snippets.json.{}", n + 1)))?;
+            }
+        }
+
+        fs::copy(path, dir.join("snippets.json.1"))?;
         Ok(())
     }
 
-    pub fn add_entry(&mut self, content: String, nickname: Option<String>) -> Result<()> {
+    /// Existing backups, newest first. Backup `n` is at index `n - 1`.
+    pub fn backups() -> Result<Vec<PathBuf>> {
+        let dir = Self::backup_dir()?;
+        Ok((1..=BACKUP_COUNT)
+            .map(|n| dir.join(format!("snippets.json.{}", n)))
+            .take_while(|path| path.exists())
+            .collect())
+    }
+
+    /// Replaces the snippet library with backup `n`. The current library is
+    /// backed up first, so a restore can itself be rolled back.
+    pub fn restore(n: usize) -> Result<Self> {
+        let backups = Self::backups()?;
+        let backup = n
+            .checked_sub(1)
+            .and_then(|i| backups.get(i))
+            .ok_or_else(|| anyhow::anyhow!("Backup {} does not exist.", n))?;
+
+        let (storage, _) = Self::read(backup)?;
+
+        let _lock = Self::lock()?;
+        storage.save()?;
+        Ok(storage)
+    }
+
+    pub fn add_entry(
+        &mut self,
+        clip: &Clip,
+        nickname: Option<String>,
+        tags: Vec<String>,
+    ) -> Result<()> {
+        let uuid = Uuid::new_v4().to_string();
+        let content = clip.text();
+        let kind = match clip {
+            Clip::Text(_) => ContentKind::Text,
+            Clip::Html { html, .. } => ContentKind::Html { html: html.clone() },
+            &Clip::Image {
+                width,
+                height,
+                ref rgba,
+            } => {
+                let path = Self::image_path(&uuid)?;
+                fs::create_dir_all(path.parent().unwrap())?;
+                write_png(&path, width, height, rgba)?;
+                ContentKind::Image { width, height }
+            }
+        };
+
         let new_entry = ClipboardEntry {
-            uuid: Uuid::new_v4().to_string(),
+            uuid,
             content,
             nickname,
+            tags,
+            created_at: unix_now(),
+            last_used_at: 0,
+            use_count: 0,
+            kind,
         };
 
-        self.entries.push(new_entry);
-        self.save()?;
+        self.modify(|storage| storage.entries.push(new_entry))
+    }
+
+    pub fn get_entry(&self, uuid: &str) -> Option<&ClipboardEntry> {
+        self.entries.iter().find(|e| e.uuid == uuid)
+    }
+
+    /// Position of the entry with `uuid` in insertion order.
+    pub fn position(&self, uuid: &str) -> Option<usize> {
+        self.entries.iter().position(|e| e.uuid == uuid)
+    }
+
+    /// Moves the entries with `uuids` to the trash, in that order.
+    pub fn remove_entries(&mut self, uuids: &[String]) -> Result<()> {
+        if let Some(uuid) = uuids.iter().find(|uuid| self.get_entry(uuid).is_none()) {
+            return Err(anyhow::anyhow!("No snippet with UUID {}.", uuid));
+        }
+        self.modify(|storage| {
+            let deleted_at = unix_now();
+            for uuid in uuids {
+                if let Some(position) = storage.position(uuid) {
+                    let entry = storage.entries.remove(position);
+                    storage.trash.push(TrashedEntry {
+                        entry,
+                        deleted_at,
+                        position,
+                    });
+                }
+            }
+        })
+    }
+
+    /// Puts trashed entries back where they were deleted from. Given in the
+    /// order they were deleted in, every entry gets its old position back.
+    pub fn restore_trashed(&mut self, uuids: &[String]) -> Result<()> {
+        if let Some(uuid) = uuids
+            .iter()
+            .find(|uuid| !self.trash.iter().any(|t| &t.entry.uuid == *uuid))
+        {
+            return Err(anyhow::anyhow!("No trashed snippet with UUID {}.", uuid));
+        }
+        self.modify(|storage| {
+            for uuid in uuids.iter().rev() {
+                if let Some(index) = storage.trash.iter().position(|t| &t.entry.uuid == uuid) {
+                    let trashed = storage.trash.remove(index);
+                    let position = trashed.position.min(storage.entries.len());
+                    storage.entries.insert(position, trashed.entry);
+                }
+            }
+        })
+    }
+
+    /// Permanently deletes trashed entries older than `retention_days`,
+    /// returning how many were purged. Saves only when there were any.
+    pub fn purge_trash(&mut self, retention_days: u64) -> Result<usize> {
+        let cutoff = unix_now().saturating_sub(retention_days.saturating_mul(24 * 60 * 60));
+        let expired = |t: &TrashedEntry| t.deleted_at <= cutoff;
+
+        let count = self.trash.iter().filter(|t| expired(t)).count();
+        if count > 0 {
+            self.modify(|storage| {
+                storage.trash.retain(|t| {
+                    if expired(t) {
+                        t.entry.remove_image();
+                    }
+                    !expired(t)
+                })
+            })?;
+        }
+        Ok(count)
+    }
+
+    pub fn empty_trash(&mut self) -> Result<()> {
+        self.modify(|storage| {
+            for trashed in storage.trash.drain(..) {
+                trashed.entry.remove_image();
+            }
+        })
+    }
+
+    /// Moves the entry with `uuid` to position `to`, clamped to the end.
+    pub fn move_entry(&mut self, uuid: &str, to: usize) -> Result<()> {
+        if self.get_entry(uuid).is_none() {
+            return Err(anyhow::anyhow!("No snippet with UUID {}.", uuid));
+        }
+        self.modify(|storage| {
+            if let Some(from) = storage.position(uuid) {
+                let entry = storage.entries.remove(from);
+                let to = to.min(storage.entries.len());
+                storage.entries.insert(to, entry);
+            }
+        })
+    }
+
+    /// Applies `change` to the entry with `uuid` and saves. Fails when there
+    /// is no such entry, e.g. because another process deleted it meanwhile.
+    pub fn update_entry(
+        &mut self,
+        uuid: &str,
+        change: impl FnOnce(&mut ClipboardEntry),
+    ) -> Result<()> {
+        let mut found = false;
+        self.modify(|storage| {
+            if let Some(entry) = storage.entries.iter_mut().find(|e| e.uuid == uuid) {
+                change(entry);
+                found = true;
+            }
+        })?;
+
+        if !found {
+            return Err(anyhow::anyhow!("No snippet with UUID {}.", uuid));
+        }
         Ok(())
     }
 
-    pub fn remove_entry(&mut self, index: usize) -> Result<()> {
-        self.entries.remove(index);
-        self.save()?;
+    /// Applies `change` to each of the entries with `uuids` that still exist,
+    /// saving once for all of them.
+    pub fn update_entries(
+        &mut self,
+        uuids: &[String],
+        mut change: impl FnMut(&mut ClipboardEntry),
+    ) -> Result<()> {
+        self.modify(|storage| {
+            for entry in storage.entries.iter_mut() {
+                if uuids.contains(&entry.uuid) {
+                    change(entry);
+                }
+            }
+        })
+    }
+
+    /// Counts a paste of each of `uuids` for frecency.
+    pub fn record_use(&mut self, uuids: &[String]) -> Result<()> {
+        let now = unix_now();
+        // Skips the backup rotation, otherwise a handful of pastes would push
+        // every real edit out of the backups
+        self.update(false, |storage| {
+            for entry in &mut storage.entries {
+                if uuids.contains(&entry.uuid) {
+                    entry.use_count += 1;
+                    entry.last_used_at = now;
+                }
+            }
+        })
+    }
+
+    /// Applies `change` to the latest library on disk while holding the store
+    /// lock, saves it and adopts it as `self`. Another cliphoard process may
+    /// have saved since we loaded, so changes are replayed by UUID on top of
+    /// its version rather than overwriting it with ours.
+    fn modify(&mut self, change: impl FnOnce(&mut Self)) -> Result<()> {
+        self.update(true, change)
+    }
+
+    /// `modify`, optionally without rotating the backups.
+    fn update(&mut self, backup: bool, change: impl FnOnce(&mut Self)) -> Result<()> {
+        let _lock = Self::lock()?;
+
+        let mut latest = Self::load()?;
+        change(&mut latest);
+        if backup {
+            latest.save()?;
+        } else {
+            write_atomic(&Self::storage_path()?, &latest)?;
+        }
+
+        *self = latest;
         Ok(())
     }
 
+    /// Takes the advisory lock guarding load-modify-save of snippets.json,
+    /// blocking until other processes release it. Dropping the file unlocks.
+    fn lock() -> Result<File> {
+        let path = Self::storage_path()?.with_extension("lock");
+        fs::create_dir_all(path.parent().unwrap())?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        file.lock()?;
+        Ok(file)
+    }
+
     pub fn get_entries(&self) -> &[ClipboardEntry] {
         &self.entries
     }
 
+    pub fn get_trash(&self) -> &[TrashedEntry] {
+        &self.trash
+    }
+
     pub fn storage_path() -> Result<PathBuf> {
         let dir = ProjectDirs::from("com", "cogStudios", "cliphoard")
             .ok_or_else(|| anyhow::anyhow!("Could not locate project directory."))?;
         Ok(dir.data_dir().join("snippets.json"))
     }
+
+    /// Where the PNG of image entry `uuid` is stored.
+    pub fn image_path(uuid: &str) -> Result<PathBuf> {
+        let dir = ProjectDirs::from("com", "cogStudios", "cliphoard")
+            .ok_or_else(|| anyhow::anyhow!("Could not locate project directory."))?;
+        Ok(dir.data_dir().join("images").join(format!("{}.png", uuid)))
+    }
+
+    pub fn backup_dir() -> Result<PathBuf> {
+        let dir = ProjectDirs::from("com", "cogStudios", "cliphoard")
+            .ok_or_else(|| anyhow::anyhow!("Could not locate project directory."))?;
+        Ok(dir.data_dir().join("backups"))
+    }
+}
+
+/// Serializes `value` into a temp file next to `path`, fsyncs it and renames
+/// it over `path`, so a crash mid-write never leaves a truncated file behind.
+pub fn write_atomic<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let parent = path.parent().unwrap();
+    fs::create_dir_all(parent)?;
+
+    let file_name = path.file_name().unwrap().to_string_lossy();
+    let tmp_path = parent.join(format!(".{}.{}.tmp", file_name, process::id()));
+
+    let result = (|| {
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        serde_json::to_writer_pretty(&mut writer, value)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        fs::rename(&tmp_path, path)?;
+
+        // Persist the rename itself
+        File::open(parent)?.sync_all()?;
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads `json` as the content of a snippets.json file.
+    fn read_json(json: &str) -> Result<(ClipboardStorage, u64)> {
+        let path = std::env::temp_dir().join(format!("cliphoard-test-{}.json", Uuid::new_v4()));
+        fs::write(&path, json)?;
+        let result = ClipboardStorage::read(&path);
+        let _ = fs::remove_file(&path);
+        result
+    }
+
+    #[test]
+    fn upgrades_every_older_version() {
+        for (json, version) in [
+            (
+                r#"{"entries": [{"uuid": "a", "content": "x", "nickname": null}]}"#,
+                1,
+            ),
+            (
+                r#"{"version": 2, "entries": [{"uuid": "a", "content": "x", "nickname": null,
+                    "tags": ["work"], "created_at": 5, "last_used_at": 0, "use_count": 3}]}"#,
+                2,
+            ),
+            (
+                r#"{"version": 3, "trash": [], "entries": [{"uuid": "a", "content": "x",
+                    "nickname": null, "tags": ["work"], "created_at": 5, "last_used_at": 0,
+                    "use_count": 3}]}"#,
+                3,
+            ),
+        ] {
+            let (storage, from) = read_json(json).unwrap();
+            assert_eq!(from, version);
+            assert_eq!(storage.version, STORAGE_VERSION);
+            assert!(storage.trash.is_empty());
+
+            let entry = &storage.entries[0];
+            assert_eq!(entry.content, "x");
+            assert_eq!(entry.kind, ContentKind::Text);
+            if version == 1 {
+                assert!(entry.tags.is_empty());
+                assert_eq!(entry.use_count, 0);
+            } else {
+                assert_eq!(entry.tags, ["work"]);
+                assert_eq!(entry.use_count, 3);
+            }
+        }
+    }
+
+    #[test]
+    fn refuses_newer_versions() {
+        let json = format!(r#"{{"version": {}, "entries": []}}"#, STORAGE_VERSION + 1);
+        let error = read_json(&json).err().unwrap();
+        assert!(error.to_string().contains("newer cliphoard"));
+    }
+
+    #[test]
+    fn reads_the_current_version_unchanged() {
+        let json = serde_json::to_string(&ClipboardStorage::new()).unwrap();
+        let (storage, from) = read_json(&json).unwrap();
+        assert_eq!(from, STORAGE_VERSION);
+        assert!(storage.entries.is_empty());
+    }
 }
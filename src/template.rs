@@ -0,0 +1,194 @@
+// Snippet templates. Placeholders are written as `{{name}}` inside a snippet's
+// content and filled in right before it is pasted:
+//
+//   {{date}} / {{date:%d.%m.%Y}}   current local date, strftime format
+//   {{clipboard}}                  text currently on the clipboard
+//   {{env:USER}}                   environment variable
+//   {{name}}                       asked for in the popup after selection
+//
+// Names asked for are letters, digits, `_` and `-`, starting with a letter or
+// `_`. Anything else between braces, like Go templates' `{{.Names}}`, and a
+// `{{` without a matching `}}` are left as-is. `\{{` is a literal `{{`.
+use chrono::Local;
+use std::{collections::HashMap, env, fmt::Write};
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Placeholder {
+    Input(String),
+    Date(String),
+    Clipboard,
+    Env(String),
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Text(String),
+    Placeholder(Placeholder),
+}
+
+#[derive(Debug, Clone)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(content: &str) -> Self {
+        let mut segments = Vec::new();
+        let mut rest = content;
+
+        while let Some(start) = rest.find("{{") {
+            if rest[..start].ends_with('\\') {
+                push_text(&mut segments, &rest[..start - 1]);
+                push_text(&mut segments, "{{");
+                rest = &rest[start + 2..];
+                continue;
+            }
+            let Some(len) = rest[start + 2..].find("}}") else {
+                break;
+            };
+            let inner = rest[start + 2..start + 2 + len].trim();
+            let end = start + 2 + len + 2;
+
+            let placeholder = match inner.split_once(':') {
+                _ if inner.is_empty() => None,
+                Some(("date", format)) => Some(Placeholder::Date(format.to_string())),
+                Some(("env", name)) => Some(Placeholder::Env(name.trim().to_string())),
+                None if inner == "date" => Some(Placeholder::Date(DEFAULT_DATE_FORMAT.into())),
+                None if inner == "clipboard" => Some(Placeholder::Clipboard),
+                None if is_name(inner) => Some(Placeholder::Input(inner.to_string())),
+                None => None,
+                Some(_) => None,
+            };
+
+            match placeholder {
+                Some(placeholder) => {
+                    push_text(&mut segments, &rest[..start]);
+                    segments.push(Segment::Placeholder(placeholder));
+                }
+                None => push_text(&mut segments, &rest[..end]),
+            }
+            rest = &rest[end..];
+        }
+        push_text(&mut segments, rest);
+
+        Template { segments }
+    }
+
+    /// Names of the placeholders the user has to type a value for, in the
+    /// order they first appear. Repeated names are only asked for once.
+    pub fn prompts(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for segment in &self.segments {
+            if let Segment::Placeholder(Placeholder::Input(name)) = segment {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+        names
+    }
+
+    pub fn render(&self, values: &HashMap<String, String>, clipboard: Option<&str>) -> String {
+        let mut output = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => output.push_str(text),
+                Segment::Placeholder(Placeholder::Input(name)) => {
+                    output.push_str(values.get(name).map_or("", |v| v.as_str()))
+                }
+                Segment::Placeholder(Placeholder::Date(format)) => {
+                    // An invalid format is an error rather than a panic here
+                    let mut date = String::new();
+                    if write!(date, "{}", Local::now().format(format)).is_err() {
+                        date = format!("{{{{date:{}}}}}", format);
+                    }
+                    output.push_str(&date);
+                }
+                Segment::Placeholder(Placeholder::Clipboard) => {
+                    output.push_str(clipboard.unwrap_or(""))
+                }
+                Segment::Placeholder(Placeholder::Env(name)) => {
+                    output.push_str(&env::var(name).unwrap_or_default())
+                }
+            }
+        }
+        output
+    }
+}
+
+/// Whether `name` can be asked for: `[A-Za-z_][A-Za-z0-9_-]*`.
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn push_text(segments: &mut Vec<Segment>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    match segments.last_mut() {
+        Some(Segment::Text(previous)) => previous.push_str(text),
+        _ => segments.push(Segment::Text(text.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(content: &str) -> String {
+        Template::parse(content).render(&HashMap::new(), Some("clip"))
+    }
+
+    #[test]
+    fn asks_for_named_placeholders_once() {
+        let template = Template::parse("{{greeting}}, {{ name }}! {{greeting}} {{user-id}}");
+        assert_eq!(template.prompts(), ["greeting", "name", "user-id"]);
+
+        let values = HashMap::from([
+            ("greeting".to_string(), "Hi".to_string()),
+            ("name".to_string(), "Ann".to_string()),
+        ]);
+        assert_eq!(template.render(&values, None), "Hi, Ann! Hi ");
+    }
+
+    #[test]
+    fn fills_in_builtin_placeholders() {
+        assert_eq!(render("[{{clipboard}}]"), "[clip]");
+        assert_eq!(render("{{date:%%}}"), "%");
+        assert_eq!(render("{{date}}").len(), "2024-01-01".len());
+        assert!(Template::parse("{{date}} {{env:HOME}}")
+            .prompts()
+            .is_empty());
+    }
+
+    #[test]
+    fn leaves_other_braces_alone() {
+        for content in [
+            "docker ps --format '{{.Names}}'",
+            "{{ .Values.image.tag }}",
+            "{% if x %}{{ x | upper }}{% endif %}",
+            "{{}} {{ }} {{1st}}",
+            "{{unclosed",
+            "}} {{a:b}}",
+        ] {
+            let template = Template::parse(content);
+            assert!(template.prompts().is_empty(), "{content}");
+            assert_eq!(template.render(&HashMap::new(), None), content);
+        }
+    }
+
+    #[test]
+    fn escaped_braces_are_literal() {
+        let template = Template::parse(r"\{{name}} {{name}}");
+        assert_eq!(template.prompts(), ["name"]);
+        let values = HashMap::from([("name".to_string(), "x".to_string())]);
+        assert_eq!(template.render(&values, None), "{{name}} x");
+        assert_eq!(render(r"\{{clipboard}}"), "{{clipboard}}");
+    }
+}
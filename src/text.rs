@@ -0,0 +1,108 @@
+// Grapheme cluster helpers for displayed and edited text, so a character as
+// the user sees it (an accented letter, a CJK glyph, an emoji with modifiers)
+// is never split by cursor movement or truncation.
+use unicode_segmentation::UnicodeSegmentation;
+
+pub const ELLIPSIS: &str = "…";
+
+pub fn graphemes(text: &str) -> Vec<&str> {
+    text.graphemes(true).collect()
+}
+
+pub fn grapheme_count(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+/// Byte offset of the grapheme boundary before byte offset `pos`.
+pub fn prev_boundary(text: &str, pos: usize) -> usize {
+    text[..pos]
+        .grapheme_indices(true)
+        .next_back()
+        .map_or(0, |(i, _)| i)
+}
+
+/// Byte offset of the grapheme boundary after byte offset `pos`.
+pub fn next_boundary(text: &str, pos: usize) -> usize {
+    text[pos..]
+        .graphemes(true)
+        .next()
+        .map_or(text.len(), |g| pos + g.len())
+}
+
+/// Longest prefix of `text` that is at most `len` bytes and ends on a
+/// grapheme boundary.
+pub fn truncate_bytes(text: &str, len: usize) -> &str {
+    if text.len() <= len {
+        return text;
+    }
+    let end = text
+        .grapheme_indices(true)
+        .map(|(i, g)| i + g.len())
+        .take_while(|&end| end <= len)
+        .last()
+        .unwrap_or(0);
+    &text[..end]
+}
+
+/// Byte offset of grapheme `index`, or the length of `text` past the end.
+pub fn byte_offset(text: &str, index: usize) -> usize {
+    text.grapheme_indices(true)
+        .nth(index)
+        .map_or(text.len(), |(i, _)| i)
+}
+
+/// Converts sorted char positions (as reported by the fuzzy matcher) into the
+/// indices of the graphemes containing them.
+pub fn grapheme_positions(text: &str, positions: &[usize]) -> Vec<usize> {
+    let mut result = Vec::new();
+    let mut remaining = positions.iter().peekable();
+    let mut chars = 0;
+
+    for (i, grapheme) in text.graphemes(true).enumerate() {
+        chars += grapheme.chars().count();
+        if remaining.peek().is_none() {
+            break;
+        }
+        if remaining.peek().is_some_and(|&&p| p < chars) {
+            result.push(i);
+            while remaining.next_if(|&&p| p < chars).is_some() {}
+        }
+    }
+    result
+}
+
+/// Number of leading `graphemes` that fit in `width` when followed by
+/// `suffix`, as measured by `measure`.
+pub fn fit_width(
+    graphemes: &[&str],
+    suffix: &str,
+    width: u32,
+    measure: impl Fn(&str) -> u32,
+) -> usize {
+    let fits = |n: usize| {
+        let text = graphemes[..n].concat() + suffix;
+        text.is_empty() || measure(&text) <= width
+    };
+
+    // binary search for the largest count that fits
+    let (mut low, mut high) = (0, graphemes.len());
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        if fits(mid) {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    low
+}
+
+/// `text` cut down to `width`, ending in an ellipsis when anything was cut.
+pub fn truncate_to_width(text: &str, width: u32, measure: impl Fn(&str) -> u32) -> String {
+    if text.is_empty() || measure(text) <= width {
+        return text.to_string();
+    }
+    let graphemes = graphemes(text);
+    let kept = fit_width(&graphemes, ELLIPSIS, width, measure);
+    graphemes[..kept].concat() + ELLIPSIS
+}
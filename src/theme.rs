@@ -0,0 +1,313 @@
+// Colors and layout of the popup. The `[theme]` table of config.toml picks a
+// bundled theme by `name` and may override any of its values, e.g.
+//
+//   [theme]
+//   name = "nord"
+//   row_padding = 12
+//   match_color = { RGB = [255, 0, 0] }
+use crate::config::{out_of_range, ColorConfig};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// Names of the bundled themes.
+pub const THEMES: &[&str] = &["default", "gruvbox", "nord", "solarized"];
+
+/// Top-level settings of older config files that now live in `[theme]`.
+pub const LEGACY_KEYS: &[&str] = &[
+    "background",
+    "cursor",
+    "selected_color",
+    "unselected_color",
+    "input_color",
+    "match_color",
+    "match_underline",
+];
+
+/// Settings that are unset unless configured, so a serialized theme lacks them.
+pub const OPTIONAL_KEYS: &[&str] = &["list_top", "row_height"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Theme {
+    pub background: ColorConfig,
+    /// Window opacity, from 0 (invisible) to 1
+    pub opacity: f32,
+    pub cursor: ColorConfig,
+    /// Text of the selected row, the preview pane and the status bar
+    pub selected_color: ColorConfig,
+    /// Text of the other rows and of the result count
+    pub unselected_color: ColorConfig,
+    pub input_color: ColorConfig,
+    /// Placeholder of the empty input line and the text being copied
+    pub placeholder_color: ColorConfig,
+    /// Background of selected text in the input line and the editor
+    pub selection_color: ColorConfig,
+    /// Color of the characters that matched the search query
+    pub match_color: ColorConfig,
+    /// Underline matched characters in addition to coloring them
+    pub match_underline: bool,
+    /// Background of the selected row
+    pub selected_row_color: ColorConfig,
+    /// Background of the other rows
+    pub row_color: ColorConfig,
+    /// Bar at the left edge of marked rows
+    pub mark_color: ColorConfig,
+    /// Line between the list and the preview pane
+    pub divider_color: ColorConfig,
+    pub status_bar_color: ColorConfig,
+    pub status_color: ColorConfig,
+    /// Space between the window edges and the text, in pixels
+    pub margin: i32,
+    /// Height of a result row beyond the height of its text
+    pub row_padding: i32,
+    /// Space between the highlights of consecutive rows
+    pub row_gap: i32,
+    /// Fixed y position of the result list, instead of below the input line
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub list_top: Option<i32>,
+    /// Fixed height of a result row, instead of the text height plus
+    /// `row_padding`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub row_height: Option<i32>,
+    /// Width of the bar marking a row
+    pub mark_width: u32,
+    /// Space between a thumbnail and the text of its row
+    pub thumbnail_gap: i32,
+    /// Space kept right of the cursor when long input scrolls sideways
+    pub scroll_margin: i32,
+    /// Space above and below the text of the status bar
+    pub status_padding: i32,
+    /// Space between the preview pane's header and the content
+    pub preview_gap: i32,
+    /// How far the divider beside the preview pane stops above the bottom
+    pub divider_inset: i32,
+    /// Width the text being copied is wrapped at
+    pub wrap_width: u32,
+    /// Share of the window width taken by the list when the preview pane is
+    /// shown, in percent
+    pub list_width_percent: i32,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            background: ColorConfig::RGBA(60, 56, 42, 80),
+            opacity: 0.5,
+            cursor: ColorConfig::RGB(20, 200, 29),
+            selected_color: ColorConfig::RGB(230, 230, 230),
+            unselected_color: ColorConfig::RGBA(230, 230, 230, 70),
+            input_color: ColorConfig::RGB(255, 255, 255),
+            placeholder_color: ColorConfig::RGBA(255, 255, 255, 90),
+            selection_color: ColorConfig::RGBA(120, 110, 80, 160),
+            match_color: ColorConfig::RGB(250, 189, 47),
+            match_underline: false,
+            selected_row_color: ColorConfig::RGB(80, 75, 56),
+            row_color: ColorConfig::RGB(60, 56, 42),
+            mark_color: ColorConfig::RGB(20, 200, 29),
+            divider_color: ColorConfig::RGBA(230, 230, 230, 70),
+            status_bar_color: ColorConfig::RGB(40, 37, 28),
+            status_color: ColorConfig::RGB(230, 230, 230),
+            margin: 12,
+            row_padding: 7,
+            row_gap: 2,
+            list_top: None,
+            row_height: None,
+            mark_width: 5,
+            thumbnail_gap: 8,
+            scroll_margin: 16,
+            status_padding: 4,
+            preview_gap: 6,
+            divider_inset: 5,
+            wrap_width: 900,
+            list_width_percent: 55,
+        }
+    }
+}
+
+impl Theme {
+    /// The bundled theme called `name`.
+    pub fn named(name: &str) -> Result<Self> {
+        let theme = match name {
+            "default" => Theme::default(),
+            "gruvbox" => Theme {
+                background: ColorConfig::RGB(40, 40, 40),
+                opacity: 0.95,
+                cursor: ColorConfig::RGB(184, 187, 38),
+                selected_color: ColorConfig::RGB(235, 219, 178),
+                unselected_color: ColorConfig::RGB(146, 131, 116),
+                input_color: ColorConfig::RGB(251, 241, 199),
+                placeholder_color: ColorConfig::RGB(124, 111, 100),
+                selection_color: ColorConfig::RGB(102, 92, 84),
+                match_color: ColorConfig::RGB(250, 189, 47),
+                selected_row_color: ColorConfig::RGB(80, 73, 69),
+                row_color: ColorConfig::RGB(60, 56, 54),
+                mark_color: ColorConfig::RGB(184, 187, 38),
+                divider_color: ColorConfig::RGB(102, 92, 84),
+                status_bar_color: ColorConfig::RGB(29, 32, 33),
+                status_color: ColorConfig::RGB(235, 219, 178),
+                ..Theme::default()
+            },
+            "nord" => Theme {
+                background: ColorConfig::RGB(46, 52, 64),
+                opacity: 0.95,
+                cursor: ColorConfig::RGB(136, 192, 208),
+                selected_color: ColorConfig::RGB(236, 239, 244),
+                unselected_color: ColorConfig::RGB(216, 222, 233),
+                input_color: ColorConfig::RGB(236, 239, 244),
+                placeholder_color: ColorConfig::RGB(97, 110, 136),
+                selection_color: ColorConfig::RGB(76, 86, 106),
+                match_color: ColorConfig::RGB(235, 203, 139),
+                selected_row_color: ColorConfig::RGB(67, 76, 94),
+                row_color: ColorConfig::RGB(59, 66, 82),
+                mark_color: ColorConfig::RGB(163, 190, 140),
+                divider_color: ColorConfig::RGB(76, 86, 106),
+                status_bar_color: ColorConfig::RGB(36, 41, 51),
+                status_color: ColorConfig::RGB(229, 233, 240),
+                ..Theme::default()
+            },
+            "solarized" => Theme {
+                background: ColorConfig::RGB(0, 43, 54),
+                opacity: 0.95,
+                cursor: ColorConfig::RGB(38, 139, 210),
+                selected_color: ColorConfig::RGB(147, 161, 161),
+                unselected_color: ColorConfig::RGB(101, 123, 131),
+                input_color: ColorConfig::RGB(238, 232, 213),
+                placeholder_color: ColorConfig::RGB(88, 110, 117),
+                selection_color: ColorConfig::RGB(88, 110, 117),
+                match_color: ColorConfig::RGB(181, 137, 0),
+                selected_row_color: ColorConfig::RGB(7, 54, 66),
+                row_color: ColorConfig::RGB(0, 43, 54),
+                mark_color: ColorConfig::RGB(133, 153, 0),
+                divider_color: ColorConfig::RGB(88, 110, 117),
+                status_bar_color: ColorConfig::RGB(7, 54, 66),
+                status_color: ColorConfig::RGB(147, 161, 161),
+                ..Theme::default()
+            },
+            _ => {
+                return Err(anyhow!(
+                    "Unknown theme '{}'. Bundled themes: {}.",
+                    name,
+                    THEMES.join(", ")
+                ))
+            }
+        };
+        Ok(theme)
+    }
+
+    /// The settings outside of what the popup can work with, and why.
+    pub fn range_problems(&self) -> Vec<(&'static str, String)> {
+        [
+            ("opacity", out_of_range("opacity", self.opacity, 0.0..=1.0)),
+            ("margin", out_of_range("margin", self.margin, 0..=200)),
+            (
+                "row_padding",
+                out_of_range("row_padding", self.row_padding, 0..=200),
+            ),
+            ("row_gap", out_of_range("row_gap", self.row_gap, 0..=50)),
+            (
+                "list_top",
+                self.list_top
+                    .and_then(|top| out_of_range("list_top", top, 0..=1000)),
+            ),
+            (
+                "row_height",
+                self.row_height
+                    .and_then(|height| out_of_range("row_height", height, 8..=500)),
+            ),
+            (
+                "mark_width",
+                out_of_range("mark_width", self.mark_width, 0..=50),
+            ),
+            (
+                "thumbnail_gap",
+                out_of_range("thumbnail_gap", self.thumbnail_gap, 0..=100),
+            ),
+            (
+                "scroll_margin",
+                out_of_range("scroll_margin", self.scroll_margin, 0..=500),
+            ),
+            (
+                "status_padding",
+                out_of_range("status_padding", self.status_padding, 0..=100),
+            ),
+            (
+                "preview_gap",
+                out_of_range("preview_gap", self.preview_gap, 0..=100),
+            ),
+            (
+                "divider_inset",
+                out_of_range("divider_inset", self.divider_inset, 0..=100),
+            ),
+            (
+                "wrap_width",
+                out_of_range("wrap_width", self.wrap_width, 100..=10_000),
+            ),
+            (
+                "list_width_percent",
+                out_of_range("list_width_percent", self.list_width_percent, 10..=90),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(key, problem)| problem.map(|message| (key, message)))
+        .collect()
+    }
+}
+
+/// The `[theme]` table as written in config.toml: the bundled theme's `name`
+/// and the values that override it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ThemeConfig(pub toml::Table);
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        let mut table = toml::Table::new();
+        table.insert("name".to_string(), "default".into());
+        Self(table)
+    }
+}
+
+impl ThemeConfig {
+    /// The named theme with the overrides applied.
+    pub fn resolve(&self) -> Result<Theme> {
+        let mut overrides = self.0.clone();
+        let name = match overrides.remove("name") {
+            Some(toml::Value::String(name)) => name,
+            Some(_) => return Err(anyhow!("`name` in [theme] must be a string.")),
+            None => "default".to_string(),
+        };
+
+        let toml::Value::Table(mut table) = toml::Value::try_from(Theme::named(&name)?)? else {
+            unreachable!("a theme serializes to a table");
+        };
+        table.extend(overrides);
+        toml::Value::Table(table)
+            .try_into()
+            .map_err(|e| anyhow!("Invalid [theme]: {}", e.to_string().trim_end()))
+    }
+
+    /// Moves the legacy top-level color settings of an older config file
+    /// into `[theme]`, unless the theme sets them itself. Values that were
+    /// just the old defaults are dropped, so they don't override a named theme.
+    pub fn migrate(config: &mut toml::Table) {
+        let Ok(toml::Value::Table(defaults)) = toml::Value::try_from(Theme::default()) else {
+            return;
+        };
+        let legacy: Vec<(String, toml::Value)> = LEGACY_KEYS
+            .iter()
+            .filter_map(|key| config.remove(*key).map(|value| (key.to_string(), value)))
+            .filter(|(key, value)| defaults.get(key) != Some(value))
+            .collect();
+        if legacy.is_empty() {
+            return;
+        }
+
+        let theme = config
+            .entry("theme")
+            .or_insert_with(|| toml::Value::Table(ThemeConfig::default().0));
+        if let toml::Value::Table(theme) = theme {
+            for (key, value) in legacy {
+                theme.entry(key).or_insert(value);
+            }
+        }
+    }
+}
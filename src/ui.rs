@@ -4,20 +4,48 @@
 // elipses (...) in paste [ DONE ]
 // user config [ DONE ]
 // styling (dividing line, transparent placeholder text) [ DONE ]
-use crate::config::{self, ColorConfig, Config};
-use crate::system::ClipboardStorage;
-use font_kit::{handle::Handle, source::SystemSource};
+use crate::config::{self, ColorConfig, Config, ListOrder};
+use crate::content::{read_png, Clip, ContentKind};
+use crate::editor::{edit_externally, LineInput, TextArea};
+use crate::fonts::FontChain;
+use crate::fuzzy::fuzzy_match;
+use crate::history::{ClipboardHistory, Selection};
+use crate::keys::Action;
+use crate::system::{
+    format_age, has_tag, unix_now, write_atomic, ClipboardEntry, ClipboardStorage,
+};
+use crate::template::Template;
+use crate::text::{
+    byte_offset, fit_width, grapheme_count, grapheme_positions, graphemes, truncate_to_width,
+    ELLIPSIS,
+};
+use crate::theme::Theme;
+use crate::undo::{Change, UndoStack};
+use arboard::LinuxClipboardKind;
+use chrono::Local;
+use directories::UserDirs;
 use sdl2::{
     clipboard::ClipboardUtil,
     event::Event,
     keyboard::{Keycode, Mod, TextInputUtil},
-    pixels::Color,
+    mouse::MouseButton,
+    pixels::{Color, PixelFormatEnum},
     rect::Rect,
-    render::Canvas,
+    render::{Canvas, Texture, TextureCreator},
+    surface::Surface,
     ttf,
-    video::Window,
+    video::{Window, WindowContext, WindowPos},
     Sdl, VideoSubsystem,
 };
+use std::{
+    collections::HashMap,
+    fs,
+    ops::Range,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How often the popup checks config.toml for changes.
+const CONFIG_POLL: Duration = Duration::from_millis(500);
 
 impl From<ColorConfig> for Color {
     fn from(value: config::ColorConfig) -> Self {
@@ -27,20 +55,105 @@ impl From<ColorConfig> for Color {
         }
     }
 }
-// TODO: add cursor position
+/// A snippet that matched the current query, with the char positions of its
+/// content that matched (empty when only the nickname matched).
+struct SnippetMatch {
+    index: usize,
+    positions: Vec<usize>,
+}
+
+/// A searchable row of the active view.
+struct ViewItem<'a> {
+    uuid: &'a str,
+    content: &'a str,
+    nickname: Option<&'a str>,
+    tags: &'a [String],
+    frecency: u64,
+}
+
+/// Where the parts of the popup go, worked out from the font and window size.
+struct Layout {
+    /// Top of the first result row
+    list_top: i32,
+    row_height: i32,
+    /// Result rows per page
+    rows: usize,
+    /// Height of the status bar, 0 while it is hidden
+    status_height: i32,
+}
+
+/// Which list the popup is searching, switched with Tab.
+#[derive(Clone, Copy, PartialEq)]
+enum View {
+    Snippets,
+    History,
+}
+
+/// Values being collected for a selected template, one placeholder at a time.
+struct TemplatePrompt {
+    content: String,
+    template: Template,
+    names: Vec<String>,
+    values: HashMap<String, String>,
+}
+
+/// A snippet open in the in-popup editor.
+struct SnippetEditor {
+    uuid: String,
+    area: TextArea,
+}
+
+/// Nickname being typed into the input buffer for an existing snippet.
+struct Renaming {
+    uuid: String,
+    /// Search query to put back once the rename is done
+    query: String,
+}
+
+/// Tags being typed into the input buffer for the marked snippets.
+struct Tagging {
+    uuids: Vec<String>,
+    /// Search query to put back once tagging is done
+    query: String,
+}
+
 pub struct DClipWindow {
     context: Sdl,
     canvas: Canvas<Window>,
-    ttf: ttf::Sdl2TtfContext,
-    input_buffer: String,
+    input: LineInput,
     text: TextInputUtil,
-    cursor_pos: usize,
-    copied_text: Option<String>,
+    copied: Option<Clip>,
+    clipboard: ClipboardUtil,
+    prompt: Option<TemplatePrompt>,
+    editor: Option<SnippetEditor>,
+    renaming: Option<Renaming>,
+    tagging: Option<Tagging>,
+    /// UUIDs of the rows marked for a bulk action, in the order they were marked
+    marked: Vec<String>,
+    /// Snippets that will be deleted once the status bar prompt is confirmed
+    confirm_delete: Option<Vec<String>>,
+    /// Message for the status bar, cleared on the next key press
+    status: Option<String>,
     snippets: ClipboardStorage,
+    undo: UndoStack,
+    history: ClipboardHistory,
+    view: View,
     selected_index: usize,
-    paste_text: Option<String>,
-    filtered_snippets: Vec<usize>,
+    paste: Option<Clip>,
+    /// UUIDs of the snippets `paste` came from, for usage stats
+    pasted_entries: Vec<String>,
+    /// Snippet to keep selected when the results are next recomputed
+    follow_entry: Option<String>,
+    filtered_snippets: Vec<SnippetMatch>,
+    show_preview: bool,
+    /// First content line shown in the preview pane
+    preview_scroll: usize,
     needs_update: bool,
+    theme: Theme,
+    /// Height of a line of text in the current font
+    line_height: i32,
+    /// Current window height, see `window_height`
+    height: i32,
     user_config: Config,
 }
 
@@ -54,84 +167,772 @@ impl DClipWindow {
 
         let clipboard: ClipboardUtil = video.clipboard();
 
-        let copied_text = if copying && clipboard.has_primary_selection_text() {
-            Some(clipboard.primary_selection_text().unwrap())
+        // Saves the highlighted text, or whatever was copied last (e.g. an
+        // image) when nothing is highlighted
+        let copied = if copying {
+            arboard::Clipboard::new().ok().and_then(|mut clipboard| {
+                Clip::read(&mut clipboard, LinuxClipboardKind::Primary)
+                    .or_else(|| Clip::read(&mut clipboard, LinuxClipboardKind::Clipboard))
+            })
         } else {
             None
         };
 
+        let theme = config.theme.resolve().expect("Invalid [theme] in config.");
         let mut window = video
             .window("", config.width as u32, config.height as u32)
             .position_centered()
             .borderless()
             .build()
             .expect("Failed to create window.");
-        let _ = window.set_opacity(0.5);
+        let _ = window.set_opacity(theme.opacity);
 
         let mut canvas = window
             .into_canvas()
             .build()
             .expect("Failed to create canvas.");
-        let _ = canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
+        canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
 
-        let ttf = ttf::init().expect("Failed to retrieve ttf context.");
         let text = video.text_input();
 
-        let snippets = ClipboardStorage::load().expect("Failed to load snippets.json");
+        let mut snippets = ClipboardStorage::load().expect("Failed to load snippets.json");
+        if let Err(e) = snippets.purge_trash(config.trash_retention_days) {
+            eprintln!("cliphoard: failed to purge trash: {e}");
+        }
+        let history = ClipboardHistory::load().expect("Failed to load history.json");
 
         DClipWindow {
             context,
             canvas,
-            ttf,
-            input_buffer: String::from(""),
+            input: LineInput::default(),
             text,
-            cursor_pos: 0,
-            copied_text,
+            copied,
+            clipboard,
+            prompt: None,
+            editor: None,
+            renaming: None,
+            tagging: None,
+            marked: Vec::new(),
+            confirm_delete: None,
+            status: None,
             snippets,
+            undo: UndoStack::default(),
+            history,
+            view: View::Snippets,
             selected_index: 0,
-            paste_text: None,
+            paste: None,
+            pasted_entries: Vec::new(),
+            follow_entry: None,
             filtered_snippets: Vec::new(),
+            show_preview: config.preview,
+            preview_scroll: 0,
             needs_update: true,
+            theme,
+            line_height: 0,
+            height: config.height,
             user_config: config,
         }
     }
 
-    fn fuzzy_find(&self, content: &str) -> bool {
-        let query = &self.input_buffer.to_lowercase();
-        let content_lower = content.to_lowercase();
-        let mut content_chars = content_lower.chars().peekable();
+    /// Every item in the active view.
+    fn view_items(&self) -> Vec<ViewItem<'_>> {
+        let now = unix_now();
+        match self.view {
+            View::Snippets => self
+                .snippets
+                .get_entries()
+                .iter()
+                .map(|e| ViewItem {
+                    uuid: &e.uuid,
+                    content: &e.content,
+                    nickname: e.nickname.as_deref(),
+                    tags: &e.tags,
+                    frecency: e.frecency(now),
+                })
+                .collect(),
+            View::History => self
+                .history
+                .get_entries()
+                .iter()
+                .map(|e| ViewItem {
+                    uuid: &e.uuid,
+                    content: &e.content,
+                    nickname: None,
+                    tags: &[],
+                    frecency: 0,
+                })
+                .collect(),
+        }
+    }
+
+    /// The highlighted snippet, when the snippets view is active.
+    fn selected_entry(&self) -> Option<&ClipboardEntry> {
+        let snippet_match = self.filtered_snippets.get(self.selected_index)?;
+        match self.view {
+            View::Snippets => self.snippets.get_entries().get(snippet_match.index),
+            View::History => None,
+        }
+    }
+
+    /// The rows a bulk action applies to: the marked ones, or else the
+    /// highlighted one.
+    fn targets(&self) -> Vec<String> {
+        if !self.marked.is_empty() {
+            return self.marked.clone();
+        }
+        let items = self.view_items();
+        self.filtered_snippets
+            .get(self.selected_index)
+            .and_then(|m| items.get(m.index))
+            .map(|item| vec![item.uuid.to_string()])
+            .unwrap_or_default()
+    }
+
+    /// Marks the row at `index` of the results, or unmarks it.
+    fn toggle_mark(&mut self, index: usize) {
+        let items = self.view_items();
+        let Some(uuid) = self
+            .filtered_snippets
+            .get(index)
+            .and_then(|m| items.get(m.index))
+            .map(|item| item.uuid.to_string())
+        else {
+            return;
+        };
+
+        match self.marked.iter().position(|u| *u == uuid) {
+            Some(i) => {
+                self.marked.remove(i);
+            }
+            None => self.marked.push(uuid),
+        }
+    }
+
+    /// Deletes the target snippets, asking for confirmation first when there
+    /// is more than one.
+    fn request_delete(&mut self) {
+        if self.view != View::Snippets {
+            return;
+        }
+        let uuids = self.targets();
+        match uuids.len() {
+            0 => {}
+            1 => self.apply_change(Change::Delete { uuids }),
+            _ => self.confirm_delete = Some(uuids),
+        }
+    }
+
+    /// Deletes the snippets waiting for confirmation, or cancels.
+    fn finish_delete(&mut self, confirmed: bool) {
+        let Some(uuids) = self.confirm_delete.take() else {
+            return;
+        };
+        if confirmed {
+            self.status = Some(format!("Deleted {} snippets.", uuids.len()));
+            self.apply_change(Change::Delete { uuids });
+            self.marked.clear();
+        }
+    }
+
+    /// Switches the input buffer to typing tags for the target snippets.
+    fn start_tagging(&mut self) {
+        let uuids = self.targets();
+        if uuids.is_empty() || self.view != View::Snippets {
+            return;
+        }
+        self.tagging = Some(Tagging {
+            uuids,
+            query: self.input.take(),
+        });
+    }
+
+    /// Leaves tagging mode, adding the typed tags when `save` is set. Tags
+    /// written as `-tag` are removed instead.
+    fn finish_tagging(&mut self, save: bool) {
+        let Some(tagging) = self.tagging.take() else {
+            return;
+        };
+        let input = self.input.replace(tagging.query);
+        self.needs_update = true;
+
+        if !save {
+            return;
+        }
+        let mut uuids = Vec::new();
+        let mut before = Vec::new();
+        let mut after = Vec::new();
+        for uuid in tagging.uuids {
+            let Some(entry) = self.snippets.get_entry(&uuid) else {
+                continue;
+            };
+            let mut tags = entry.tags.clone();
+            for word in input.split_whitespace() {
+                let word = word.trim_start_matches('#');
+                match word.strip_prefix('-') {
+                    Some(tag) => tags.retain(|t| !t.eq_ignore_ascii_case(tag)),
+                    None if !word.is_empty()
+                        && !tags.iter().any(|t| t.eq_ignore_ascii_case(word)) =>
+                    {
+                        tags.push(word.to_string())
+                    }
+                    None => {}
+                }
+            }
+            if tags != entry.tags {
+                before.push(entry.tags.clone());
+                after.push(tags);
+                uuids.push(uuid);
+            }
+        }
+
+        if !uuids.is_empty() {
+            self.status = Some(format!("Retagged {} snippets.", uuids.len()));
+            self.apply_change(Change::Tag {
+                uuids,
+                before,
+                after,
+            });
+            self.marked.clear();
+        }
+    }
+
+    /// Writes the target rows to a JSON file in the home directory.
+    fn export_targets(&mut self) {
+        let uuids = self.targets();
+        if uuids.is_empty() {
+            return;
+        }
+        let Some(dirs) = UserDirs::new() else {
+            self.status = Some(String::from("Could not locate the home directory."));
+            return;
+        };
+        let path = dirs.home_dir().join(format!(
+            "cliphoard-export-{}.json",
+            Local::now().format("%Y%m%d-%H%M%S")
+        ));
+
+        let result = match self.view {
+            View::Snippets => {
+                let entries: Vec<&ClipboardEntry> = uuids
+                    .iter()
+                    .filter_map(|uuid| self.snippets.get_entry(uuid))
+                    .collect();
+                write_atomic(&path, &entries)
+            }
+            View::History => {
+                let entries: Vec<_> = uuids
+                    .iter()
+                    .filter_map(|uuid| self.history.get_entries().iter().find(|e| e.uuid == *uuid))
+                    .collect();
+                write_atomic(&path, &entries)
+            }
+        };
+
+        self.status = Some(match result {
+            Ok(()) => format!("Exported {} entries to {}", uuids.len(), path.display()),
+            Err(e) => format!("Export failed: {e}"),
+        });
+    }
+
+    /// Joins the marked rows into one paste, in the order they were marked.
+    /// Returns true once `paste` is final. Images can't be joined, so marked
+    /// image snippets are refused.
+    fn paste_marked(&mut self) -> bool {
+        if self.view == View::Snippets
+            && self.marked.iter().any(|uuid| {
+                self.snippets
+                    .get_entry(uuid)
+                    .is_some_and(|e| matches!(e.kind, ContentKind::Image { .. }))
+            })
+        {
+            self.status = Some(String::from(
+                "Images can't be pasted together with other rows",
+            ));
+            return false;
+        }
+
+        let items = self.view_items();
+        let content = self
+            .marked
+            .iter()
+            .filter_map(|uuid| items.iter().find(|item| item.uuid == uuid))
+            .map(|item| item.content)
+            .collect::<Vec<_>>()
+            .join("\n");
 
-        for qc in query.chars() {
-            loop {
-                match content_chars.next() {
-                    Some(sc) if sc == qc => break,
-                    Some(_) => continue,
-                    None => return false,
+        match self.view {
+            View::Snippets => {
+                self.pasted_entries = self.marked.clone();
+                self.paste_template(content)
+            }
+            View::History => {
+                self.pasted_entries.clear();
+                self.paste = Some(Clip::Text(content));
+                true
+            }
+        }
+    }
+
+    /// Text for the status bar at the bottom of the list, if there is any.
+    fn status_line(&self) -> Option<String> {
+        if let Some(uuids) = &self.confirm_delete {
+            return Some(format!(
+                "Delete {} snippets? Return to confirm, Esc to cancel",
+                uuids.len()
+            ));
+        }
+        if self.status.is_some() {
+            return self.status.clone();
+        }
+        if !self.marked.is_empty() {
+            return Some(format!(
+                "{} marked: Return pastes them, Ctrl+D deletes, Ctrl+T tags, Ctrl+S exports",
+                self.marked.len()
+            ));
+        }
+        None
+    }
+
+    /// Width of the result list, which leaves room for the preview pane when
+    /// that is shown.
+    fn list_width(&self) -> i32 {
+        if self.show_preview {
+            self.user_config.width * self.theme.list_width_percent / 100
+        } else {
+            self.user_config.width
+        }
+    }
+
+    /// Header lines and full content of the highlighted row, for the
+    /// preview pane.
+    fn preview_details(&self) -> Option<(Vec<String>, String)> {
+        let snippet_match = self.filtered_snippets.get(self.selected_index)?;
+        let now = unix_now();
+
+        match self.view {
+            View::Snippets => {
+                let entry = self.snippets.get_entries().get(snippet_match.index)?;
+                let mut header = vec![entry
+                    .nickname
+                    .clone()
+                    .unwrap_or_else(|| String::from("(no nickname)"))];
+                if !entry.tags.is_empty() {
+                    let tags: Vec<String> = entry.tags.iter().map(|t| format!("#{t}")).collect();
+                    header.push(tags.join(" "));
+                }
+
+                let mut details = Vec::new();
+                if entry.created_at > 0 {
+                    details.push(format!(
+                        "added {}",
+                        format_age(now.saturating_sub(entry.created_at))
+                    ));
                 }
+                details.push(match entry.use_count {
+                    0 => String::from("never used"),
+                    1 => String::from("used once"),
+                    n => format!("used {n} times"),
+                });
+                if entry.use_count > 0 && entry.last_used_at > 0 {
+                    details.push(format!(
+                        "last {}",
+                        format_age(now.saturating_sub(entry.last_used_at))
+                    ));
+                }
+                header.push(details.join(", "));
+
+                Some((header, entry.content.clone()))
+            }
+            View::History => {
+                let entry = self.history.get_entries().get(snippet_match.index)?;
+                let selection = match entry.selection {
+                    Selection::Clipboard => "clipboard",
+                    Selection::Primary => "primary selection",
+                };
+                let header = vec![format!(
+                    "copied {} from the {}",
+                    format_age(now.saturating_sub(entry.copied_at)),
+                    selection
+                )];
+                Some((header, entry.content.clone()))
             }
         }
+    }
+
+    fn layout(&self) -> Layout {
+        let margin = self.theme.margin;
+        let list_top = self
+            .theme
+            .list_top
+            .unwrap_or(margin + self.line_height + margin / 2);
+        let row_height = self
+            .theme
+            .row_height
+            .unwrap_or(self.line_height + self.theme.row_padding);
+        let status_height = match self.status_line() {
+            Some(_) => self.line_height + 2 * self.theme.status_padding,
+            None => 0,
+        };
+
+        let max_rows = self.user_config.max_rows.max(1);
+        let rows = if self.user_config.auto_height {
+            max_rows
+        } else {
+            let fitting = (self.user_config.height - list_top - status_height) / row_height.max(1);
+            fitting.clamp(1, max_rows as i32) as usize
+        };
+
+        Layout {
+            list_top,
+            row_height,
+            rows,
+            status_height,
+        }
+    }
+
+    /// Height the window should have: the configured one, or with
+    /// `auto_height` just enough for the rows shown.
+    fn window_height(&self) -> i32 {
+        if !self.user_config.auto_height {
+            return self.user_config.height;
+        }
+        let layout = self.layout();
+        let rows = if self.browsing() && !self.show_preview {
+            let start = self.selected_index / layout.rows * layout.rows;
+            (self.filtered_snippets.len().saturating_sub(start)).clamp(1, layout.rows)
+        } else {
+            layout.rows
+        };
+        layout.list_top
+            + rows as i32 * layout.row_height
+            + layout.status_height
+            + self.theme.margin / 2
+    }
+
+    /// Whether the list shows every snippet in the saved order, which needs
+    /// `list_order = "saved"` and no search. Otherwise the ranking decides
+    /// where rows go, and moving one would not show.
+    fn in_saved_order(&self) -> bool {
+        self.view == View::Snippets
+            && self.input.is_empty()
+            && self.filtered_snippets.len() == self.snippets.get_entries().len()
+            && self
+                .filtered_snippets
+                .iter()
+                .enumerate()
+                .all(|(i, m)| m.index == i)
+    }
+
+    /// Moves the highlighted snippet one place up or down in the saved order,
+    /// keeping it selected.
+    fn move_selected(&mut self, down: bool) {
+        if !self.in_saved_order() {
+            self.status = Some(String::from(match self.user_config.list_order {
+                ListOrder::Frecency => "Set list_order = \"saved\" in config.toml to move snippets",
+                ListOrder::Saved => "Clear the search to move snippets",
+            }));
+            return;
+        }
+        let Some(entry) = self.selected_entry() else {
+            return;
+        };
+        let uuid = entry.uuid.clone();
+        let Some(position) = self.snippets.position(&uuid) else {
+            return;
+        };
+        let to = if down {
+            position + 1
+        } else if position > 0 {
+            position - 1
+        } else {
+            return;
+        };
+
+        self.apply_change(Change::Move {
+            uuid,
+            from: position,
+            to,
+        });
+    }
+
+    /// Applies `change` to the snippets and records it for undo.
+    fn apply_change(&mut self, change: Change) {
+        match change.apply(&mut self.snippets) {
+            Ok(()) => {
+                self.follow_entry = Some(change.uuid().to_string());
+                self.undo.push(change);
+            }
+            Err(e) => eprintln!("cliphoard: {e}"),
+        }
+        self.needs_update = true;
+    }
+
+    /// Undoes the latest change, or redoes the latest undone one.
+    fn undo_change(&mut self, redo: bool) {
+        let result = if redo {
+            self.undo.redo(&mut self.snippets)
+        } else {
+            self.undo.undo(&mut self.snippets)
+        };
+
+        match result {
+            Ok(uuid) => self.follow_entry = uuid,
+            Err(e) => eprintln!("cliphoard: {e}"),
+        }
+        self.needs_update = true;
+    }
+
+    /// True while the result list is shown, as opposed to the copy route, a
+    /// template prompt or the editor.
+    fn browsing(&self) -> bool {
+        self.copied.is_none()
+            && self.prompt.is_none()
+            && self.editor.is_none()
+            && self.renaming.is_none()
+            && self.tagging.is_none()
+            && self.confirm_delete.is_none()
+    }
+
+    /// Switches the input buffer to editing the selected snippet's nickname.
+    fn start_rename(&mut self) {
+        let Some(entry) = self.selected_entry() else {
+            return;
+        };
+        let uuid = entry.uuid.clone();
+        let nickname = entry.nickname.clone().unwrap_or_default();
+
+        self.renaming = Some(Renaming {
+            uuid,
+            query: self.input.replace(nickname),
+        });
+    }
+
+    /// Leaves rename mode, saving the typed nickname when `save` is set.
+    fn finish_rename(&mut self, save: bool) {
+        let Some(renaming) = self.renaming.take() else {
+            return;
+        };
+        let nickname = self.input.replace(renaming.query);
+        self.needs_update = true;
+
+        if save {
+            let Some(entry) = self.snippets.get_entry(&renaming.uuid) else {
+                return;
+            };
+            let before = entry.nickname.clone();
+            let after = Some(nickname.trim().to_string()).filter(|n| !n.is_empty());
+            if after != before {
+                self.apply_change(Change::Rename {
+                    uuid: renaming.uuid,
+                    before,
+                    after,
+                });
+            }
+        }
+    }
+
+    /// Opens the selected snippet in the in-popup editor, or in the external
+    /// editor when `external_editor` is set.
+    fn open_editor(&mut self) {
+        let Some(entry) = self.selected_entry() else {
+            return;
+        };
+        // the text of an HTML snippet is editable, its markup is kept as is
+        if let ContentKind::Image { .. } = entry.kind {
+            self.status = Some(String::from("Image snippets can't be edited."));
+            return;
+        }
+        let uuid = entry.uuid.clone();
+        let content = entry.content.clone();
+
+        if !self.user_config.external_editor {
+            self.editor = Some(SnippetEditor {
+                uuid,
+                area: TextArea::new(&content),
+            });
+            return;
+        }
+
+        self.canvas.window_mut().hide();
+        match edit_externally(&content, &self.user_config.editor_command) {
+            Ok(edited) if edited != content => self.apply_change(Change::Edit {
+                uuid,
+                before: content,
+                after: edited,
+            }),
+            Ok(_) => {}
+            Err(e) => eprintln!("cliphoard: {e}"),
+        }
+        self.canvas.window_mut().show();
+        self.canvas.window_mut().raise();
+    }
+
+    /// Handles a key press while the editor is open.
+    fn editor_key(&mut self, key: Keycode, keymod: Mod) {
+        let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+        let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+
+        match key {
+            Keycode::Escape => {
+                self.editor = None;
+                return;
+            }
+            Keycode::S if ctrl => {
+                if let Some(editor) = self.editor.take() {
+                    let Some(entry) = self.snippets.get_entry(&editor.uuid) else {
+                        return;
+                    };
+                    let before = entry.content.clone();
+                    let after = editor.area.text();
+                    if after != before {
+                        self.apply_change(Change::Edit {
+                            uuid: editor.uuid,
+                            before,
+                            after,
+                        });
+                    }
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        let Some(editor) = &mut self.editor else {
+            return;
+        };
+        let area = &mut editor.area;
+        match key {
+            Keycode::Return | Keycode::KpEnter => area.insert("\n"),
+            Keycode::Backspace => area.backspace(),
+            Keycode::Delete => area.delete(),
+            Keycode::Left => area.move_left(shift),
+            Keycode::Right => area.move_right(shift),
+            Keycode::Up => area.move_up(shift),
+            Keycode::Down => area.move_down(shift),
+            Keycode::Home => area.home(shift),
+            Keycode::End => area.end(shift),
+            Keycode::A if ctrl => area.select_all(),
+            Keycode::C | Keycode::X if ctrl => {
+                if let Some(selected) = area.selected_text() {
+                    let _ = self.clipboard.set_clipboard_text(&selected);
+                    if key == Keycode::X {
+                        area.delete();
+                    }
+                }
+            }
+            Keycode::V if ctrl => {
+                if let Ok(text) = self.clipboard.clipboard_text() {
+                    area.insert(&text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Prepares a snippet for pasting. Templates with placeholders the user
+    /// has to fill in switch the popup to prompting for them first; returns
+    /// true once `paste` is final.
+    fn paste_template(&mut self, content: String) -> bool {
+        let template = Template::parse(&content);
+        let names = template.prompts();
+
+        if names.is_empty() {
+            let clipboard = self.clipboard.clipboard_text().ok();
+            self.paste = Some(Clip::Text(
+                template.render(&HashMap::new(), clipboard.as_deref()),
+            ));
+            return true;
+        }
+
+        self.prompt = Some(TemplatePrompt {
+            content,
+            template,
+            names,
+            values: HashMap::new(),
+        });
+        self.input.take();
+        false
+    }
+
+    /// Stores the typed value for the current placeholder. Returns true once
+    /// every placeholder is filled and `paste` holds the rendered text.
+    fn answer_prompt(&mut self) -> bool {
+        let Some(prompt) = &mut self.prompt else {
+            return false;
+        };
+
+        let name = prompt.names[prompt.values.len()].clone();
+        prompt.values.insert(name, self.input.take());
+
+        if prompt.values.len() < prompt.names.len() {
+            return false;
+        }
+
+        let clipboard = self.clipboard.clipboard_text().ok();
+        self.paste = Some(Clip::Text(
+            prompt.template.render(&prompt.values, clipboard.as_deref()),
+        ));
         true
     }
 
-    pub fn launch(&mut self) -> Option<String> {
-        let creator = self.canvas.texture_creator();
+    /// Resizes the window when the configured size or, with `auto_height`,
+    /// the number of rows shown changed.
+    fn resize_window(&mut self) {
+        let width = self.user_config.width;
+        let height = self.window_height();
+        if (width as u32, height as u32) != self.canvas.window().size() {
+            let _ = self
+                .canvas
+                .window_mut()
+                .set_size(width as u32, height as u32);
+        }
+        self.height = height;
+    }
 
-        let source = SystemSource::new();
-        let fonts = source
-            .all_fonts()
-            .expect("Failed to retrieve list of fonts.");
-        let selected_font = fonts.get(0).expect("No fonts found.");
-        let mut font_path = String::from("");
-        if let Handle::Path { path, .. } = selected_font {
-            font_path = path.to_str().expect("Failed to get font path.").to_string();
+    /// Applies config.toml again after it changed on disk, keeping the
+    /// current settings when it is invalid. Returns whether the font settings
+    /// changed, which the caller has to reload the fonts for.
+    fn reload_config(&mut self) -> anyhow::Result<bool> {
+        let (config, problems) = Config::load_checked()?;
+        let theme = config.theme.resolve()?;
+        if !problems.is_empty() {
+            self.status = Some(format!(
+                "config.toml has {} problem(s), see `cliphoard config check`",
+                problems.len()
+            ));
         }
 
-        let mut font = self
-            .ttf
-            .load_font(font_path, self.user_config.font_size)
-            .expect("Failed to load font.");
+        let old = &self.user_config;
+        let fonts_changed = config.font != old.font
+            || config.font_size != old.font_size
+            || config.font_weight != old.font_weight
+            || config.font_style != old.font_style
+            || config.font_fallbacks != old.font_fallbacks;
+
+        let _ = self.canvas.window_mut().set_opacity(theme.opacity);
+
+        self.theme = theme;
+        self.user_config = config;
+        self.needs_update = true;
+        Ok(fonts_changed)
+    }
 
-        font.set_style(sdl2::ttf::FontStyle::BOLD);
+    pub fn launch(&mut self) -> Option<Clip> {
+        let creator = self.canvas.texture_creator();
+        // Thumbnails of image snippets by UUID, `None` when loading failed
+        let mut thumbnails: HashMap<String, Option<Texture>> = HashMap::new();
+        // Content shown in the preview pane and its wrapped lines
+        let mut preview_lines: Option<(String, Vec<String>)> = None;
+
+        // Fonts borrow the ttf context, keeping it out of `self` lets the loop
+        // call `&mut self` methods while they are in use
+        let ttf = ttf::init().expect("Failed to retrieve ttf context.");
+        let mut font = FontChain::load(&ttf, &self.user_config).expect("Failed to load font.");
+        self.line_height = font.height();
+        self.resize_window();
+        self.canvas
+            .window_mut()
+            .set_position(WindowPos::Centered, WindowPos::Centered);
 
         let mut event_pump = self
             .context
@@ -139,265 +940,712 @@ impl DClipWindow {
             .expect("Failed to create event pump.");
 
         self.text.start();
+        let mut config_modified = config_modified_at();
+        let mut config_checked = Instant::now();
+
         'running: loop {
-            self.canvas.set_draw_color(self.user_config.background); // background color
+            if config_checked.elapsed() >= CONFIG_POLL {
+                config_checked = Instant::now();
+                let modified = config_modified_at();
+                if modified != config_modified {
+                    config_modified = modified;
+                    match self.reload_config() {
+                        Ok(fonts_changed) => {
+                            if fonts_changed {
+                                match FontChain::load(&ttf, &self.user_config) {
+                                    Ok(chain) => {
+                                        font = chain;
+                                        self.line_height = font.height();
+                                    }
+                                    Err(e) => self.status = Some(format!("Font not loaded: {e}")),
+                                }
+                            }
+                            preview_lines = None;
+                            thumbnails.clear();
+                        }
+                        Err(e) => self.status = Some(format!("config.toml not applied: {e}")),
+                    }
+                }
+            }
+
+            self.resize_window();
+            self.canvas.set_draw_color(self.theme.background); // background color
             self.canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
             self.canvas.clear();
             for event in event_pump.poll_iter() {
+                if let Event::KeyDown { .. } = event {
+                    self.status = None;
+                }
+                let action = match event {
+                    Event::KeyDown {
+                        keycode: Some(key),
+                        keymod,
+                        ..
+                    } => self.user_config.keymap.action(key, keymod),
+                    _ => None,
+                };
                 match event {
-                    Event::Quit { .. }
-                    | Event::KeyDown {
-                        keycode: Some(Keycode::Escape),
+                    Event::KeyDown {
+                        keycode: Some(key),
+                        keymod,
+                        ..
+                    } if self.editor.is_some() => self.editor_key(key, keymod),
+                    Event::TextInput { text, .. } if self.editor.is_some() => {
+                        if let Some(editor) = &mut self.editor {
+                            editor.area.insert(&text);
+                        }
+                    }
+                    Event::KeyDown { .. } if self.confirm_delete.is_some() => {
+                        self.finish_delete(action == Some(Action::Select));
+                    }
+                    Event::TextInput { .. } if self.confirm_delete.is_some() => {}
+                    Event::KeyDown { .. }
+                        if action == Some(Action::Quit) && self.renaming.is_some() =>
+                    {
+                        self.finish_rename(false)
+                    }
+                    Event::KeyDown { .. }
+                        if action == Some(Action::Quit) && self.tagging.is_some() =>
+                    {
+                        self.finish_tagging(false)
+                    }
+                    Event::KeyDown { .. }
+                        if action == Some(Action::Quit)
+                            && !self.marked.is_empty()
+                            && self.browsing() =>
+                    {
+                        self.marked.clear()
+                    }
+                    Event::MouseButtonDown {
+                        mouse_btn: MouseButton::Left,
+                        x,
+                        y,
                         ..
-                    } => break 'running,
+                    } if self.browsing()
+                        && y >= self.layout().list_top
+                        && x < self.list_width() =>
+                    {
+                        let layout = self.layout();
+                        let page_size = layout.rows;
+                        let row = ((y - layout.list_top) / layout.row_height) as usize;
+                        let index = self.selected_index / page_size * page_size + row;
+                        if row < page_size && index < self.filtered_snippets.len() {
+                            let keymod = self.context.keyboard().mod_state();
+                            if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) {
+                                self.toggle_mark(index);
+                            } else {
+                                self.selected_index = index;
+                            }
+                        }
+                    }
+                    Event::KeyDown { .. }
+                        if action == Some(Action::Quit) && self.prompt.is_some() =>
+                    {
+                        // back out of the template prompt to the result list
+                        self.prompt = None;
+                        self.pasted_entries.clear();
+                        self.input.take();
+                        self.needs_update = true;
+                    }
+                    Event::Quit { .. } => break 'running,
+                    Event::KeyDown { .. } if action == Some(Action::Quit) => break 'running,
                     Event::KeyDown {
-                        keycode, keymod, ..
-                    } => {
-                        if let Some(key) = keycode {
-                            match key {
-                                Keycode::Backspace => {
-                                    if self.cursor_pos != 0 {
-                                        self.cursor_pos -= 1;
-                                        let _ = self.input_buffer.remove(self.cursor_pos);
-                                        self.needs_update = true;
-                                    }
-                                }
-                                Keycode::D => {
-                                    if keymod == Mod::LCTRLMOD
-                                        && self.snippets.get_entries().len() != 0
-                                    {
-                                        let _ = self.selected_index = 0;
-                                        let _ = self.snippets.remove_entry(self.selected_index);
-                                        self.needs_update = true;
+                        keycode: Some(key),
+                        keymod,
+                        ..
+                    } => match action {
+                        Some(Action::Delete) if self.browsing() => {
+                            self.request_delete();
+                        }
+                        Some(Action::Tag) if self.browsing() => {
+                            self.start_tagging();
+                        }
+                        Some(Action::Export) if self.browsing() => {
+                            self.export_targets();
+                        }
+                        Some(Action::Mark) if self.browsing() => {
+                            self.toggle_mark(self.selected_index);
+                        }
+                        Some(Action::Undo) if self.browsing() => {
+                            self.undo_change(false);
+                        }
+                        Some(Action::Redo) if self.browsing() => {
+                            self.undo_change(true);
+                        }
+                        Some(Action::Edit) if self.browsing() && self.view == View::Snippets => {
+                            self.open_editor();
+                        }
+                        Some(Action::Rename) if self.browsing() && self.view == View::Snippets => {
+                            self.start_rename();
+                        }
+                        Some(Action::MoveUp) if self.browsing() => {
+                            self.move_selected(false);
+                        }
+                        Some(Action::MoveDown) if self.browsing() => {
+                            self.move_selected(true);
+                        }
+                        Some(Action::Up) if self.browsing() => {
+                            if self.selected_index > 0 {
+                                self.selected_index -= 1;
+                            } else {
+                                self.selected_index =
+                                    self.filtered_snippets.len().saturating_sub(1);
+                            }
+                        }
+                        Some(Action::Down) if self.browsing() => {
+                            if self.selected_index < self.filtered_snippets.len().saturating_sub(1)
+                            {
+                                self.selected_index += 1;
+                            } else {
+                                self.selected_index = 0;
+                            }
+                        }
+                        Some(Action::TogglePreview) if self.browsing() => {
+                            self.show_preview = !self.show_preview;
+                        }
+                        Some(Action::PreviewUp) if self.show_preview => {
+                            self.preview_scroll = self.preview_scroll.saturating_sub(3);
+                        }
+                        Some(Action::PreviewDown) if self.show_preview => {
+                            self.preview_scroll += 3;
+                        }
+                        Some(Action::SwitchView) if self.browsing() => {
+                            self.view = match self.view {
+                                View::Snippets => View::History,
+                                View::History => View::Snippets,
+                            };
+                            self.selected_index = 0;
+                            self.marked.clear();
+                            self.needs_update = true;
+                        }
+                        Some(Action::Select) => {
+                            // copy route
+                            if let Some(copied) = &self.copied {
+                                let (nickname, tags) = split_tags(self.input.text());
+                                let nickname = Some(nickname).filter(|n| !n.is_empty());
+                                // stay open on failure so the copy isn't lost
+                                match self.snippets.add_entry(copied, nickname, tags) {
+                                    Ok(()) => break 'running,
+                                    Err(e) => {
+                                        eprintln!("cliphoard: {e}");
+                                        self.status = Some(format!("Could not save snippet: {e}"));
                                     }
                                 }
-                                Keycode::Left => {
-                                    if self.cursor_pos != 0 {
-                                        self.cursor_pos -= 1;
-                                    }
+                            } else if self.renaming.is_some() {
+                                self.finish_rename(true);
+                            } else if self.tagging.is_some() {
+                                self.finish_tagging(true);
+                            } else if !self.marked.is_empty() && self.prompt.is_none() {
+                                if self.paste_marked() {
+                                    break 'running;
                                 }
-                                Keycode::Right => {
-                                    if self.cursor_pos != self.input_buffer.chars().count() {
-                                        self.cursor_pos += 1;
-                                    }
+                            } else if self.prompt.is_some() {
+                                if self.answer_prompt() {
+                                    break 'running;
                                 }
-                                Keycode::UP => {
-                                    if self.copied_text.is_none() {
-                                        if self.selected_index > 0 {
-                                            self.selected_index -= 1;
-                                        } else {
-                                            self.selected_index =
-                                                self.filtered_snippets.len().saturating_sub(1);
+                            } else if let Some(snippet_match) =
+                                self.filtered_snippets.get(self.selected_index)
+                            {
+                                if let Some(item) = self.view_items().get(snippet_match.index) {
+                                    let content = item.content.to_string();
+                                    match self.view {
+                                        View::Snippets => {
+                                            let entry =
+                                                &self.snippets.get_entries()[snippet_match.index];
+                                            self.pasted_entries = vec![entry.uuid.clone()];
+
+                                            // only plain text can be a template
+                                            if entry.kind == ContentKind::Text {
+                                                if self.paste_template(content) {
+                                                    break 'running;
+                                                }
+                                            } else {
+                                                match entry.clip() {
+                                                    Ok(clip) => {
+                                                        self.paste = Some(clip);
+                                                        break 'running;
+                                                    }
+                                                    Err(e) => {
+                                                        self.pasted_entries.clear();
+                                                        self.status =
+                                                            Some(format!("Could not paste: {e}"))
+                                                    }
+                                                }
+                                            }
                                         }
-                                    }
-                                }
-                                Keycode::DOWN => {
-                                    if self.copied_text.is_none() {
-                                        if self.selected_index
-                                            < self.filtered_snippets.len().saturating_sub(1)
-                                        {
-                                            self.selected_index += 1;
-                                        } else {
-                                            self.selected_index = 0;
+                                        View::History => {
+                                            self.pasted_entries.clear();
+                                            self.paste = Some(Clip::Text(content));
+                                            break 'running;
                                         }
                                     }
                                 }
-                                Keycode::Return => {
-                                    // copy route
-                                    if self.copied_text.is_some() {
-                                        let nickname = Some(self.input_buffer.clone());
-                                        let _ = self
-                                            .snippets
-                                            .add_entry(self.copied_text.clone().unwrap(), nickname);
-
-                                        break 'running;
-                                    } else {
-                                        if let Some(&snippet_index) =
-                                            self.filtered_snippets.get(self.selected_index)
-                                        {
-                                            if let Some(selected_snippet) =
-                                                self.snippets.get_entries().get(snippet_index)
-                                            {
-                                                self.paste_text =
-                                                    Some(selected_snippet.content.clone());
-                                                break 'running;
-                                            }
-                                        }
+                            }
+                        }
+                        // keys of the input line, used when no action applies
+                        _ => {
+                            let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+                            let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                            match key {
+                                Keycode::Backspace => {
+                                    self.input.backspace();
+                                    self.needs_update = true;
+                                }
+                                Keycode::Delete => {
+                                    self.input.delete();
+                                    self.needs_update = true;
+                                }
+                                Keycode::W if ctrl => {
+                                    self.input.delete_word();
+                                    self.needs_update = true;
+                                }
+                                Keycode::U if ctrl => {
+                                    self.input.delete_to_start();
+                                    self.needs_update = true;
+                                }
+                                Keycode::K if ctrl => {
+                                    self.input.delete_to_end();
+                                    self.needs_update = true;
+                                }
+                                Keycode::V if ctrl => {
+                                    if let Ok(text) = self.clipboard.clipboard_text() {
+                                        self.input.insert(&text);
+                                        self.needs_update = true;
                                     }
                                 }
+                                Keycode::Left if ctrl => self.input.word_left(shift),
+                                Keycode::Right if ctrl => self.input.word_right(shift),
+                                Keycode::Left => self.input.move_left(shift),
+                                Keycode::Right => self.input.move_right(shift),
+                                Keycode::Home => self.input.home(shift),
+                                Keycode::End => self.input.end(shift),
                                 _ => {}
                             }
                         }
+                    },
+                    Event::MouseWheel { y, .. } if self.show_preview => {
+                        self.preview_scroll =
+                            self.preview_scroll.saturating_add_signed(-3 * y as isize);
                     }
                     Event::TextInput { text, .. } => {
-                        // get the first char of the string (text)
-                        self.input_buffer
-                            .insert(self.cursor_pos, text.chars().next().unwrap());
-                        self.cursor_pos += 1;
+                        // may hold several chars, e.g. from an input method
+                        self.input.insert(&text);
                         self.needs_update = true;
                     }
                     _ => {}
                 }
             }
 
-            if self.needs_update {
-                let mut matches: Vec<(usize, bool)> = Vec::new();
-                for (i, snippet) in self.snippets.get_entries().iter().enumerate() {
-                    let nickname_match = snippet
-                        .nickname
-                        .as_ref()
-                        .map_or(false, |n| self.fuzzy_find(n));
-
-                    let content_match = self.fuzzy_find(&snippet.content);
+            if self.needs_update
+                && self.prompt.is_none()
+                && self.editor.is_none()
+                && self.renaming.is_none()
+                && self.tagging.is_none()
+            {
+                // "#work ssh" searches for "ssh" among snippets tagged work
+                let (query, tags) = split_tags(self.input.text());
 
-                    if nickname_match || content_match {
-                        matches.push((i, nickname_match));
+                let mut matches: Vec<(i32, i32, u64, SnippetMatch)> = Vec::new();
+                for (i, item) in self.view_items().into_iter().enumerate() {
+                    if !tags.iter().all(|tag| has_tag(item.tags, tag)) {
+                        continue;
                     }
+
+                    let nickname_match = item.nickname.and_then(|n| fuzzy_match(&query, n));
+                    let content_match = fuzzy_match(&query, item.content);
+
+                    let nickname_score = nickname_match.as_ref().map(|m| m.score);
+                    let content_score = content_match.as_ref().map(|m| m.score);
+                    let Some(score) = nickname_score.max(content_score) else {
+                        continue;
+                    };
+
+                    // without a query every nickname "matches", so leave the
+                    // ordering to frecency or the saved order alone
+                    let nickname_score = if query.is_empty() {
+                        0
+                    } else {
+                        nickname_score.unwrap_or(i32::MIN)
+                    };
+
+                    matches.push((
+                        score,
+                        nickname_score,
+                        match self.user_config.list_order {
+                            ListOrder::Frecency => item.frecency,
+                            ListOrder::Saved => 0,
+                        },
+                        SnippetMatch {
+                            index: i,
+                            positions: content_match.map(|m| m.positions).unwrap_or_default(),
+                        },
+                    ));
                 }
 
-                // Prioritize nickname search
-                matches.sort_by(|a, b| b.1.cmp(&a.1));
+                // Rank by best score, then nickname score, then frecency
+                matches.sort_by_key(|&(score, nickname_score, frecency, _)| {
+                    std::cmp::Reverse((score, nickname_score, frecency))
+                });
 
-                self.filtered_snippets = matches.into_iter().map(|(i, _)| i).collect();
+                self.filtered_snippets = matches.into_iter().map(|(_, _, _, m)| m).collect();
 
+                if let Some(uuid) = self.follow_entry.take() {
+                    let entries = self.snippets.get_entries();
+                    if let Some(index) = self
+                        .filtered_snippets
+                        .iter()
+                        .position(|m| entries[m.index].uuid == uuid)
+                    {
+                        self.selected_index = index;
+                    }
+                }
                 self.selected_index = self
                     .selected_index
                     .min(self.filtered_snippets.len().saturating_sub(1));
                 self.needs_update = false;
             }
 
-            if !self.input_buffer.is_empty() {
+            let layout = self.layout();
+            if !self.input.is_empty() && self.editor.is_none() {
                 // TODO: refactor out all the text drawing functions
-                // render input_buffer text
+                // render input text
+                let text = self.input.text();
+                let width_to = |end: usize| match &text[..end] {
+                    "" => 0,
+                    prefix => font.size_of(prefix).map_or(0, |(w, _)| w as i32),
+                };
 
-                let surface = font
-                    .render(&self.input_buffer)
-                    .blended(self.user_config.input_color) // input text color
-                    .expect("Failed to render text.");
+                let margin = self.theme.margin;
 
-                let texture = creator
-                    .create_texture_from_surface(&surface)
-                    .expect("Failed to create texture.");
+                // scroll sideways to keep the cursor in view, like the editor
+                let visible_width = self.user_config.width - 2 * margin - self.theme.scroll_margin;
+                let cursor_x = width_to(self.input.cursor());
+                let x_offset = (cursor_x - visible_width).max(0);
+
+                if let Some(selection) = self.input.selection() {
+                    let x1 = width_to(selection.start);
+                    let x2 = width_to(selection.end);
+                    self.canvas.set_draw_color(self.theme.selection_color);
+                    let _ = self.canvas.fill_rect(Rect::new(
+                        margin + x1 - x_offset,
+                        margin,
+                        (x2 - x1) as u32,
+                        font.height() as u32,
+                    ));
+                }
 
                 // render cursor
-                let rect = Rect::new(12, 12, surface.width(), surface.height());
-                let (text_width, _) = font
-                    .size_of(&self.input_buffer[0..self.cursor_pos])
-                    .unwrap();
-                let cursor = Rect::new(12 + text_width as i32, rect.y, 2, font.height() as u32);
-                self.canvas.set_draw_color(self.user_config.cursor);
+                let cursor = Rect::new(
+                    margin + cursor_x - x_offset,
+                    margin,
+                    2,
+                    font.height() as u32,
+                );
+                self.canvas.set_draw_color(self.theme.cursor);
                 let _ = self.canvas.fill_rect(cursor);
 
-                let _ = self
-                    .canvas
-                    .copy(&texture, None, Some(rect))
-                    .expect("Failed to copy to canvas.");
+                // only the part in view, a long paste would not fit in a texture
+                let (visible, x) = visible_range(
+                    &font,
+                    text,
+                    x_offset,
+                    visible_width + self.theme.scroll_margin,
+                );
+                if !visible.is_empty() {
+                    let surface = font
+                        .render(&text[visible], self.theme.input_color)
+                        .expect("Failed to render text.");
+
+                    let texture = creator
+                        .create_texture_from_surface(&surface)
+                        .expect("Failed to create texture.");
+
+                    let rect = Rect::new(
+                        margin + x - x_offset,
+                        margin,
+                        surface.width(),
+                        surface.height(),
+                    );
+                    self.canvas
+                        .copy(&texture, None, Some(rect))
+                        .expect("Failed to copy to canvas.");
+                }
             } else {
                 let mut placeholder = String::from("Type to search");
-                if self.copied_text.is_some() {
-                    placeholder = String::from("Add a nickname");
+                if self.copied.is_some() {
+                    placeholder = String::from("Add a nickname and #tags");
+                } else if self.renaming.is_some() {
+                    placeholder = String::from("New nickname, empty to clear it");
+                } else if let Some(tagging) = &self.tagging {
+                    placeholder = format!(
+                        "Tags to add to {} snippets, -tag removes one",
+                        tagging.uuids.len()
+                    );
+                } else if self.editor.is_some() {
+                    placeholder = String::from("Editing snippet, Ctrl+S to save, Esc to cancel");
+                } else if let Some(prompt) = &self.prompt {
+                    placeholder = format!(
+                        "{} ({}/{})",
+                        prompt.names[prompt.values.len()],
+                        prompt.values.len() + 1,
+                        prompt.names.len()
+                    );
+                } else if self.view == View::History {
+                    placeholder = String::from("Search clipboard history");
                 }
 
                 let surface = font
-                    .render(&placeholder)
-                    .blended(Color::RGBA(255, 255, 255, 90)) // placeholder text color
+                    .render(&placeholder, self.theme.placeholder_color)
                     .expect("Failed to render text.");
 
                 let texture = creator
                     .create_texture_from_surface(&surface)
                     .expect("Failed to create texture.");
 
-                let rect = Rect::new(12, 12, surface.width(), surface.height());
+                let margin = self.theme.margin;
+                let rect = Rect::new(margin, margin, surface.width(), surface.height());
 
-                let cursor = Rect::new(12, 12, 2, font.height() as u32);
+                let cursor = Rect::new(margin, margin, 2, font.height() as u32);
+                self.canvas.set_draw_color(self.theme.cursor);
                 let _ = self.canvas.fill_rect(cursor);
-                let _ = self
-                    .canvas
+                self.canvas
                     .copy(&texture, None, Some(rect))
                     .expect("Failed to copy to canvas.");
             }
 
-            if self.copied_text.is_some() {
-                // render the selected text persistently
-                if let Some(selected) = &self.copied_text {
-                    let copy_surface = font
-                        .render(selected)
-                        .blended_wrapped(Color::RGBA(255, 255, 255, 90), 900) // copy text color
-                        .expect("Failed to render text.");
+            let copied_text = self.copied.as_ref().map(Clip::text);
+            let pinned_text = match &self.prompt {
+                Some(prompt) => Some(&prompt.content),
+                None => copied_text.as_ref(),
+            };
+            if let Some(editor) = &mut self.editor {
+                let area = &mut editor.area;
+                let line_height = font.height();
+                let top = layout.list_top;
+                let margin = self.theme.margin;
+                let visible_rows = ((self.height - top) / line_height).max(1);
+                area.scroll_to_cursor(visible_rows as usize);
 
-                    let texture = creator
-                        .create_texture_from_surface(&copy_surface)
-                        .expect("Failed to create texture.");
+                // scroll sideways to keep the cursor in view on long lines
+                let cursor = area.cursor();
+                let cursor_x = prefix_width(&font, &area.lines()[cursor.row], cursor.col);
+                let x_offset = (cursor_x
+                    - (self.user_config.width - 2 * margin - self.theme.scroll_margin))
+                    .max(0);
 
-                    let rect = Rect::new(12, 60, copy_surface.width(), copy_surface.height());
+                let selection = area.selection();
+                for (row, line) in area
+                    .lines()
+                    .iter()
+                    .enumerate()
+                    .skip(area.scroll)
+                    .take(visible_rows as usize)
+                {
+                    let y = top + (row - area.scroll) as i32 * line_height;
 
-                    let _ = self
-                        .canvas
-                        .copy(&texture, None, Some(rect))
+                    if let Some((start, end)) =
+                        selection.filter(|(s, e)| (s.row..=e.row).contains(&row))
+                    {
+                        let from = if row == start.row { start.col } else { 0 };
+                        let (to, newline) = if row == end.row {
+                            (end.col, 0)
+                        } else {
+                            // show the selected line break as the width of a space
+                            let space = font.size_of(" ").map_or(0, |(w, _)| w as i32);
+                            (grapheme_count(line), space)
+                        };
+                        let x1 = prefix_width(&font, line, from);
+                        let x2 = prefix_width(&font, line, to) + newline;
+                        self.canvas.set_draw_color(self.theme.selection_color);
+                        let _ = self.canvas.fill_rect(Rect::new(
+                            margin + x1 - x_offset,
+                            y,
+                            (x2 - x1).max(0) as u32,
+                            line_height as u32,
+                        ));
+                    }
+
+                    let (visible, x) =
+                        visible_range(&font, line, x_offset, self.user_config.width - margin);
+                    if visible.is_empty() {
+                        continue;
+                    }
+                    let surface = font
+                        .render(&line[visible], self.theme.input_color)
+                        .expect("Failed to render text.");
+                    let texture = creator
+                        .create_texture_from_surface(&surface)
+                        .expect("Failed to create texture.");
+                    self.canvas
+                        .copy(
+                            &texture,
+                            None,
+                            Some(Rect::new(
+                                margin + x - x_offset,
+                                y,
+                                surface.width(),
+                                surface.height(),
+                            )),
+                        )
                         .expect("Failed to copy to canvas.");
                 }
+
+                self.canvas.set_draw_color(self.theme.cursor);
+                let _ = self.canvas.fill_rect(Rect::new(
+                    margin + cursor_x - x_offset,
+                    top + (cursor.row - area.scroll) as i32 * line_height,
+                    2,
+                    line_height as u32,
+                ));
+            } else if pinned_text.is_some() {
+                // render the selected text persistently
+                if let Some(selected) = pinned_text {
+                    let line_height = font.height();
+                    for (i, line) in wrap_text(&font, selected, self.theme.wrap_width)
+                        .iter()
+                        .enumerate()
+                    {
+                        draw_text(
+                            &mut self.canvas,
+                            &creator,
+                            &font,
+                            line,
+                            self.theme.placeholder_color,
+                            self.theme.margin,
+                            layout.list_top + i as i32 * line_height,
+                        );
+                    }
+                }
             } else {
                 let active_snippets = &self.filtered_snippets;
                 let snippets_count = active_snippets.len();
-                let current_page = self.selected_index / 5;
-                let start_index = current_page * 5;
-                let end_index = std::cmp::min(start_index + 5, snippets_count);
-                let mut y: i32 = 50;
-                for (page_index, &snippet_index) in self.filtered_snippets[start_index..end_index]
+                let page_size = layout.rows;
+                let current_page = self.selected_index / page_size;
+                let start_index = current_page * page_size;
+                let end_index = std::cmp::min(start_index + page_size, snippets_count);
+                let list_width = self.list_width();
+                let row_height = layout.row_height - self.theme.row_gap;
+                let mut top = layout.list_top;
+                for (page_index, snippet_match) in self.filtered_snippets[start_index..end_index]
                     .iter()
                     .enumerate()
                 {
-                    let snippet = &self.snippets.get_entries()[snippet_index];
+                    let content = match self.view {
+                        View::Snippets => &self.snippets.get_entries()[snippet_match.index].content,
+                        View::History => &self.history.get_entries()[snippet_match.index].content,
+                    };
                     let global_index = start_index + page_index;
                     let color = if global_index == self.selected_index {
-                        self.user_config.selected_color // selected text color
+                        self.theme.selected_color
                     } else {
-                        self.user_config.unselected_color // unselected text color
+                        self.theme.unselected_color
                     };
 
-                    // truncate longer snippets
-                    let rendered_snippet = if snippet.content.len() > 50 {
-                        format!("{}...", &snippet.content[..50])
-                    } else {
-                        snippet.content.clone()
-                    };
-
-                    let copy_surface = font
-                        .render(&rendered_snippet)
-                        .blended(color)
-                        .expect("Failed to render text.");
-
-                    let texture = creator
-                        .create_texture_from_surface(&copy_surface)
-                        .expect("Failed to create texture.");
-
-                    let highlighter = Rect::new(0, y - 5, 1000, copy_surface.height() + 5);
+                    // text centered in the row's highlight
+                    let y = top + (row_height - font.height()) / 2;
+                    let highlighter =
+                        Rect::new(0, top, list_width as u32, row_height.max(0) as u32);
 
                     self.canvas
                         .set_draw_color(if global_index == self.selected_index {
-                            Color::RGB(80, 75, 56) // selected highlight color
+                            self.theme.selected_row_color
                         } else {
-                            Color::RGB(60, 56, 42) // unselected highlight color
+                            self.theme.row_color
                         });
                     self.canvas.fill_rect(highlighter).unwrap();
 
-                    let rect = Rect::new(12, y, copy_surface.width(), copy_surface.height());
-                    let _ = self
-                        .canvas
-                        .copy(&texture, None, Some(rect))
-                        .expect("Failed to copy to canvas.");
+                    let uuid = match self.view {
+                        View::Snippets => &self.snippets.get_entries()[snippet_match.index].uuid,
+                        View::History => &self.history.get_entries()[snippet_match.index].uuid,
+                    };
+                    if self.marked.contains(uuid) {
+                        self.canvas.set_draw_color(self.theme.mark_color);
+                        let _ = self.canvas.fill_rect(Rect::new(
+                            0,
+                            top,
+                            self.theme.mark_width,
+                            row_height.max(0) as u32,
+                        ));
+                    }
+
+                    self.canvas.set_clip_rect(Rect::new(
+                        0,
+                        0,
+                        list_width as u32,
+                        self.height as u32,
+                    ));
+                    let mut x = self.theme.margin;
+                    if let View::Snippets = self.view {
+                        let entry = &self.snippets.get_entries()[snippet_match.index];
+                        if let ContentKind::Image { width, height } = entry.kind {
+                            let thumbnail = thumbnails
+                                .entry(entry.uuid.clone())
+                                .or_insert_with(|| load_thumbnail(&creator, &entry.uuid));
+                            if let Some(texture) = thumbnail {
+                                let h = font.height() as u32;
+                                let w = (width as u32 * h / height.max(1) as u32).clamp(1, 4 * h);
+                                let _ =
+                                    self.canvas.copy(texture, None, Some(Rect::new(x, y, w, h)));
+                                x += w as i32 + self.theme.thumbnail_gap;
+                            }
+                        }
+                    }
+
+                    // truncate longer snippets around the first match
+                    let (rendered_snippet, positions) = row_preview(
+                        &font,
+                        content,
+                        &snippet_match.positions,
+                        (list_width - x - self.theme.margin).max(0) as u32,
+                    );
+
+                    // draw matched chars in the match color
+                    for (run, matched) in highlight_runs(&rendered_snippet, &positions) {
+                        if matched && self.theme.match_underline {
+                            font.set_style(sdl2::ttf::FontStyle::UNDERLINE);
+                        }
+
+                        let copy_surface = font
+                            .render(
+                                &run,
+                                if matched {
+                                    self.theme.match_color
+                                } else {
+                                    color
+                                },
+                            )
+                            .expect("Failed to render text.");
+                        font.set_style(sdl2::ttf::FontStyle::NORMAL);
 
-                    y += 35;
+                        let texture = creator
+                            .create_texture_from_surface(&copy_surface)
+                            .expect("Failed to create texture.");
 
+                        let rect = Rect::new(x, y, copy_surface.width(), copy_surface.height());
+                        self.canvas
+                            .copy(&texture, None, Some(rect))
+                            .expect("Failed to copy to canvas.");
+                        x += copy_surface.width() as i32;
+                    }
+                    self.canvas.set_clip_rect(None);
+
+                    top += layout.row_height;
+                }
+
+                if snippets_count > 0 {
                     let index_label = format!(
-                        "{}/{}",
+                        "{}{}/{}",
+                        if self.view == View::History {
+                            "history "
+                        } else {
+                            ""
+                        },
                         &self.selected_index.saturating_add(1),
                         &self.filtered_snippets.len()
                     );
 
                     let copy_surface = font
-                        .render(&index_label)
-                        .blended(self.user_config.unselected_color)
+                        .render(&index_label, self.theme.unselected_color)
                         .expect("Failed to render text.");
 
                     let texture = creator
@@ -405,20 +1653,341 @@ impl DClipWindow {
                         .expect("Failed to create texture.");
 
                     let label_rect = Rect::new(
-                        1000 - copy_surface.width() as i32 - 10,
-                        5,
+                        self.user_config.width - copy_surface.width() as i32 - self.theme.margin,
+                        self.theme.margin,
                         copy_surface.width(),
-                        copy_surface.height() + 5,
+                        copy_surface.height(),
                     );
-                    let _ = self
-                        .canvas
+                    self.canvas
                         .copy(&texture, None, Some(label_rect))
                         .expect("Failed to copy to canvas.");
                 }
+
+                if let Some((header, content)) =
+                    self.preview_details().filter(|_| self.show_preview)
+                {
+                    let left = list_width + self.theme.margin;
+                    let pane_width = (self.user_config.width - left - self.theme.margin).max(1);
+                    let top = layout.list_top;
+                    let bottom = self.height - layout.status_height;
+                    let line_height = font.height();
+
+                    self.canvas.set_draw_color(self.theme.divider_color);
+                    let _ = self.canvas.fill_rect(Rect::new(
+                        list_width,
+                        top,
+                        1,
+                        (bottom - top - self.theme.divider_inset).max(0) as u32,
+                    ));
+
+                    if preview_lines
+                        .as_ref()
+                        .is_none_or(|(shown, _)| *shown != content)
+                    {
+                        let lines = wrap_text(&font, &content, pane_width as u32);
+                        preview_lines = Some((content, lines));
+                        self.preview_scroll = 0;
+                    }
+                    let lines = &preview_lines.as_ref().unwrap().1;
+
+                    self.canvas.set_clip_rect(Rect::new(
+                        left,
+                        top,
+                        pane_width as u32,
+                        (bottom - top).max(0) as u32,
+                    ));
+                    let mut y = top;
+                    for line in &header {
+                        let line = truncate_to_width(line, pane_width as u32, |text| {
+                            font.size_of(text).map_or(u32::MAX, |(w, _)| w)
+                        });
+                        draw_text(
+                            &mut self.canvas,
+                            &creator,
+                            &font,
+                            &line,
+                            self.theme.unselected_color,
+                            left,
+                            y,
+                        );
+                        y += line_height;
+                    }
+                    y += self.theme.preview_gap;
+
+                    let visible_rows = ((bottom - y) / line_height).max(1) as usize;
+                    self.preview_scroll = self
+                        .preview_scroll
+                        .min(lines.len().saturating_sub(visible_rows));
+                    for line in lines.iter().skip(self.preview_scroll).take(visible_rows) {
+                        draw_text(
+                            &mut self.canvas,
+                            &creator,
+                            &font,
+                            line,
+                            self.theme.selected_color,
+                            left,
+                            y,
+                        );
+                        y += line_height;
+                    }
+                    self.canvas.set_clip_rect(None);
+                }
+
+                if let Some(status) = self.status_line() {
+                    let bar_height = layout.status_height;
+                    let bar_y = self.height - bar_height;
+                    self.canvas.set_draw_color(self.theme.status_bar_color);
+                    let _ = self.canvas.fill_rect(Rect::new(
+                        0,
+                        bar_y,
+                        self.user_config.width as u32,
+                        bar_height as u32,
+                    ));
+
+                    let status = truncate_to_width(
+                        &status,
+                        (self.user_config.width - 2 * self.theme.margin).max(0) as u32,
+                        |text| font.size_of(text).map_or(u32::MAX, |(w, _)| w),
+                    );
+                    let surface = font
+                        .render(&status, self.theme.status_color)
+                        .expect("Failed to render text.");
+                    let texture = creator
+                        .create_texture_from_surface(&surface)
+                        .expect("Failed to create texture.");
+                    self.canvas
+                        .copy(
+                            &texture,
+                            None,
+                            Some(Rect::new(
+                                self.theme.margin,
+                                bar_y + self.theme.status_padding,
+                                surface.width(),
+                                surface.height(),
+                            )),
+                        )
+                        .expect("Failed to copy to canvas.");
+                }
             }
 
             self.canvas.present();
         }
-        self.paste_text.clone()
+
+        if self.paste.is_some() && !self.pasted_entries.is_empty() {
+            let _ = self.snippets.record_use(&self.pasted_entries);
+        }
+        self.paste.take()
+    }
+}
+
+/// When config.toml was last changed, if it exists.
+fn config_modified_at() -> Option<SystemTime> {
+    fs::metadata(Config::config_path())
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Loads the stored image of snippet `uuid`, shrunk to thumbnail size.
+fn load_thumbnail<'a>(
+    creator: &'a TextureCreator<WindowContext>,
+    uuid: &str,
+) -> Option<Texture<'a>> {
+    const MAX_HEIGHT: usize = 64;
+
+    let path = ClipboardStorage::image_path(uuid).ok()?;
+    let (width, height, rgba) = read_png(&path).ok()?;
+
+    // nearest-neighbour downscale, so huge screenshots don't become huge textures
+    let scale = height.div_ceil(MAX_HEIGHT).max(1);
+    let (thumb_width, thumb_height) = ((width / scale).max(1), (height / scale).max(1));
+    let mut pixels = Vec::with_capacity(thumb_width * thumb_height * 4);
+    for row in 0..thumb_height {
+        for col in 0..thumb_width {
+            let i = ((row * scale) * width + col * scale) * 4;
+            pixels.extend_from_slice(&rgba[i..i + 4]);
+        }
+    }
+
+    let surface = Surface::from_data(
+        &mut pixels,
+        thumb_width as u32,
+        thumb_height as u32,
+        thumb_width as u32 * 4,
+        PixelFormatEnum::RGBA32,
+    )
+    .ok()?;
+    creator.create_texture_from_surface(&surface).ok()
+}
+
+/// Renders one line of text with its top left corner at (`x`, `y`).
+fn draw_text(
+    canvas: &mut Canvas<Window>,
+    creator: &TextureCreator<WindowContext>,
+    font: &FontChain,
+    text: &str,
+    color: impl Into<Color>,
+    x: i32,
+    y: i32,
+) {
+    if text.is_empty() {
+        return;
+    }
+    let surface = font.render(text, color).expect("Failed to render text.");
+    let texture = creator
+        .create_texture_from_surface(&surface)
+        .expect("Failed to create texture.");
+    canvas
+        .copy(
+            &texture,
+            None,
+            Some(Rect::new(x, y, surface.width(), surface.height())),
+        )
+        .expect("Failed to copy to canvas.");
+}
+
+/// Word-wraps `text` into lines no wider than `width` pixels. Words wider
+/// than that are broken wherever they overflow.
+fn wrap_text(font: &FontChain, text: &str, width: u32) -> Vec<String> {
+    let fits = |line: &str| {
+        let line = line.trim_end();
+        line.is_empty() || font.size_of(line).is_ok_and(|(w, _)| w <= width)
+    };
+
+    let mut lines = Vec::new();
+    for paragraph in text.replace('\t', "    ").split('\n') {
+        let mut line = String::new();
+        for word in paragraph.split_inclusive(' ') {
+            if fits(&format!("{line}{word}")) {
+                line.push_str(word);
+                continue;
+            }
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            for grapheme in graphemes(word) {
+                if !line.is_empty() && !fits(&format!("{line}{grapheme}")) {
+                    lines.push(std::mem::take(&mut line));
+                }
+                line.push_str(grapheme);
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Rendered width of the first `col` graphemes of `line`.
+fn prefix_width(font: &FontChain, line: &str, col: usize) -> i32 {
+    let prefix = &line[..byte_offset(line, col)];
+    font.size_of(prefix).map_or(0, |(w, _)| w as i32)
+}
+
+/// Byte range of the graphemes of `line` drawn at least partly within
+/// `width` pixels from `from`, and the x position where the range starts.
+fn visible_range(font: &FontChain, line: &str, from: i32, width: i32) -> (Range<usize>, i32) {
+    let measure = |text: &str| font.size_of(text).map_or(0, |(w, _)| w);
+    let graphemes = graphemes(line);
+
+    let first = fit_width(&graphemes, "", from.max(0) as u32, measure);
+    let start = graphemes[..first].concat().len();
+    let x = measure(&line[..start]) as i32;
+    let shown = fit_width(
+        &graphemes[first..],
+        "",
+        (from + width - x).max(0) as u32,
+        measure,
+    );
+    let last = (first + shown + 1).min(graphemes.len());
+    let end = start + graphemes[first..last].concat().len();
+    (start..end, x)
+}
+
+/// Separates `#tag` words from the rest of `input`, which is returned with
+/// its words joined by single spaces.
+fn split_tags(input: &str) -> (String, Vec<String>) {
+    let mut words = Vec::new();
+    let mut tags: Vec<String> = Vec::new();
+
+    for word in input.split_whitespace() {
+        match word.strip_prefix('#') {
+            Some(tag) if !tag.is_empty() => {
+                if !tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                    tags.push(tag.to_string());
+                }
+            }
+            _ => words.push(word),
+        }
+    }
+
+    (words.join(" "), tags)
+}
+
+/// Cuts `content` down to a single row at most `width` pixels wide, ending
+/// in an ellipsis when anything was cut. When the first matched char would
+/// be cut off, the row starts shortly before it instead. Returns the row along
+/// with the indices of its matched graphemes.
+fn row_preview(
+    font: &FontChain,
+    content: &str,
+    positions: &[usize],
+    width: u32,
+) -> (String, Vec<usize>) {
+    // Graphemes shown before a match that had to be scrolled into view
+    const CONTEXT: usize = 10;
+    // Far more chars than fit in a row, so huge snippets aren't measured whole
+    const MAX_CHARS: usize = 400;
+
+    let measure = |text: &str| match text {
+        "" => 0,
+        text => font.size_of(text).map_or(u32::MAX, |(w, _)| w),
+    };
+
+    // one char for one, so the matched char positions stay valid
+    let limit = positions.first().copied().unwrap_or(0) + MAX_CHARS;
+    let cut_short = content.chars().nth(limit).is_some();
+    let line: String = content
+        .chars()
+        .take(limit)
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    let matched = grapheme_positions(&line, positions);
+    let graphemes = graphemes(&line);
+
+    let first = matched.first().copied().unwrap_or(0);
+    let (start, prefix) = if first > 0 && fit_width(&graphemes, ELLIPSIS, width, measure) <= first {
+        (first.saturating_sub(CONTEXT), ELLIPSIS)
+    } else {
+        (0, "")
+    };
+
+    let rest = &graphemes[start..];
+    let room = width.saturating_sub(measure(prefix));
+    let mut row = prefix.to_string() + &rest.concat();
+    let mut kept = rest.len();
+    if cut_short || measure(&row) > width {
+        kept = fit_width(rest, ELLIPSIS, room, measure);
+        row = prefix.to_string() + &rest[..kept].concat() + ELLIPSIS;
+    }
+
+    let offset = grapheme_count(prefix);
+    let positions = matched
+        .into_iter()
+        .filter(|&g| (start..start + kept).contains(&g))
+        .map(|g| g - start + offset)
+        .collect();
+    (row, positions)
+}
+
+/// Splits `text` into runs of consecutive graphemes that are either all
+/// matched or all unmatched, so each run can be rendered in its own color.
+fn highlight_runs(text: &str, positions: &[usize]) -> Vec<(String, bool)> {
+    let mut runs: Vec<(String, bool)> = Vec::new();
+    for (i, grapheme) in graphemes(text).into_iter().enumerate() {
+        let matched = positions.binary_search(&i).is_ok();
+        match runs.last_mut() {
+            Some((run, run_matched)) if *run_matched == matched => run.push_str(grapheme),
+            _ => runs.push((grapheme.to_string(), matched)),
+        }
     }
+    runs
 }
@@ -0,0 +1,121 @@
+// Undo/redo of the changes made to snippets from the popup. Changes refer to
+// snippets by UUID, so they stay valid when the list is reordered or filtered.
+use crate::system::ClipboardStorage;
+use anyhow::Result;
+
+pub enum Change {
+    /// Moved to the trash, undone by restoring from there
+    Delete { uuids: Vec<String> },
+    Edit {
+        uuid: String,
+        before: String,
+        after: String,
+    },
+    Rename {
+        uuid: String,
+        before: Option<String>,
+        after: Option<String>,
+    },
+    Tag {
+        uuids: Vec<String>,
+        /// Tags of each snippet in `uuids`
+        before: Vec<Vec<String>>,
+        after: Vec<Vec<String>>,
+    },
+    Move {
+        uuid: String,
+        from: usize,
+        to: usize,
+    },
+}
+
+impl Change {
+    /// The (first) snippet the change touches.
+    pub fn uuid(&self) -> &str {
+        match self {
+            Change::Delete { uuids } | Change::Tag { uuids, .. } => {
+                uuids.first().map_or("", String::as_str)
+            }
+            Change::Edit { uuid, .. } | Change::Rename { uuid, .. } | Change::Move { uuid, .. } => {
+                uuid
+            }
+        }
+    }
+
+    /// Makes the change, used both the first time and to redo it.
+    pub fn apply(&self, storage: &mut ClipboardStorage) -> Result<()> {
+        match self {
+            Change::Delete { uuids } => storage.remove_entries(uuids),
+            Change::Edit { uuid, after, .. } => {
+                storage.update_entry(uuid, |entry| entry.content = after.clone())
+            }
+            Change::Rename { uuid, after, .. } => {
+                storage.update_entry(uuid, |entry| entry.nickname = after.clone())
+            }
+            Change::Tag { uuids, after, .. } => set_tags(storage, uuids, after),
+            Change::Move { uuid, to, .. } => storage.move_entry(uuid, *to),
+        }
+    }
+
+    fn revert(&self, storage: &mut ClipboardStorage) -> Result<()> {
+        match self {
+            Change::Delete { uuids } => storage.restore_trashed(uuids),
+            Change::Edit { uuid, before, .. } => {
+                storage.update_entry(uuid, |entry| entry.content = before.clone())
+            }
+            Change::Rename { uuid, before, .. } => {
+                storage.update_entry(uuid, |entry| entry.nickname = before.clone())
+            }
+            Change::Tag { uuids, before, .. } => set_tags(storage, uuids, before),
+            Change::Move { uuid, from, .. } => storage.move_entry(uuid, *from),
+        }
+    }
+}
+
+fn set_tags(storage: &mut ClipboardStorage, uuids: &[String], tags: &[Vec<String>]) -> Result<()> {
+    storage.update_entries(uuids, |entry| {
+        if let Some(i) = uuids.iter().position(|u| *u == entry.uuid) {
+            entry.tags = tags[i].clone();
+        }
+    })
+}
+
+#[derive(Default)]
+pub struct UndoStack {
+    done: Vec<Change>,
+    undone: Vec<Change>,
+}
+
+impl UndoStack {
+    /// Records a change that was just applied. This forgets anything undone.
+    pub fn push(&mut self, change: Change) {
+        self.undone.clear();
+        self.done.push(change);
+    }
+
+    /// Reverts the latest change and returns the UUID of the snippet it
+    /// touched. A change that can no longer be reverted, e.g. because its
+    /// snippet was purged from the trash, is dropped.
+    pub fn undo(&mut self, storage: &mut ClipboardStorage) -> Result<Option<String>> {
+        let Some(change) = self.done.pop() else {
+            return Ok(None);
+        };
+        change.revert(storage)?;
+
+        let uuid = change.uuid().to_string();
+        self.undone.push(change);
+        Ok(Some(uuid))
+    }
+
+    /// Applies the latest undone change again.
+    pub fn redo(&mut self, storage: &mut ClipboardStorage) -> Result<Option<String>> {
+        let Some(change) = self.undone.pop() else {
+            return Ok(None);
+        };
+        change.apply(storage)?;
+
+        let uuid = change.uuid().to_string();
+        self.done.push(change);
+        Ok(Some(uuid))
+    }
+}